@@ -0,0 +1,63 @@
+# cosmicterm shell integration for bash, loaded with --rcfile in place of
+# ~/.bashrc. Marks prompts and commands (OSC 133), and reports the current
+# directory (OSC 7) and the title (OSC 2).
+
+if [[ -f ~/.bashrc ]]; then
+    source ~/.bashrc
+fi
+
+if [[ $- != *i* || -n $__cosmicterm_loaded ]]; then
+    return
+fi
+__cosmicterm_loaded=1
+
+# PS0 and ${var@a} came with bash 4.4. Older versions, like the bash 3.2 of
+# macOS, only get the A and D marks.
+__cosmicterm_ps0=0
+if (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 4) )); then
+    __cosmicterm_ps0=1
+fi
+
+__cosmicterm_save_status() {
+    __cosmicterm_status=$?
+}
+
+__cosmicterm_prompt() {
+    # Only a command that ran is marked as done, not an empty command line
+    if [[ -n $__cosmicterm_executing ]]; then
+        printf '\e]133;D;%s\a' "$__cosmicterm_status"
+    fi
+    __cosmicterm_executing=
+    printf '\e]133;A\a'
+
+    local dir=${PWD//%/%25}
+    dir=${dir// /%20}
+    printf '\e]7;file://%s%s\a' "$HOSTNAME" "$dir"
+    printf '\e]2;%s\a' "${PWD/#$HOME/\~}"
+
+    # Prompts that are rebuilt before each prompt lose the marks, so they are
+    # added back every time. PS0 is only shown when a command runs, and the
+    # subscript flags it from there without printing anything. Without PS0,
+    # every prompt but the first ends a command.
+    if (( __cosmicterm_ps0 )); then
+        if [[ $PS1 != *'133;B'* ]]; then
+            PS1+='\[\e]133;B\a\]'
+        fi
+        if [[ $PS0 != *'133;C'* ]]; then
+            PS0+='${__cosmicterm_executing[__cosmicterm_executing=1]:0:0}\e]133;C\a'
+        fi
+    else
+        __cosmicterm_executing=1
+    fi
+    return "$__cosmicterm_status"
+}
+
+# The status is saved before the other prompt commands run, and the marks are
+# emitted after them. Since bash 5.1, PROMPT_COMMAND may be an array of
+# commands. Otherwise newlines separate them, as the commands may already end
+# with a semicolon.
+if (( __cosmicterm_ps0 )) && [[ ${PROMPT_COMMAND@a} == *a* ]]; then
+    PROMPT_COMMAND=(__cosmicterm_save_status "${PROMPT_COMMAND[@]}" __cosmicterm_prompt)
+else
+    PROMPT_COMMAND=$'__cosmicterm_save_status\n'"${PROMPT_COMMAND:+$PROMPT_COMMAND$'\n'}"'__cosmicterm_prompt'
+fi
@@ -0,0 +1,41 @@
+# cosmicterm shell integration for fish, found through XDG_DATA_DIRS. Marks
+# prompts and commands (OSC 133) and reports the current directory (OSC 7).
+# fish already sets the title itself.
+
+# Don't pass the integration on to other programs
+if set -q COSMICTERM_ORIG_XDG_DATA_DIRS
+    set -gx XDG_DATA_DIRS $COSMICTERM_ORIG_XDG_DATA_DIRS
+    set -e COSMICTERM_ORIG_XDG_DATA_DIRS
+else
+    set -e XDG_DATA_DIRS
+end
+
+if not status is-interactive; or set -q __cosmicterm_loaded
+    exit
+end
+set -g __cosmicterm_loaded 1
+
+function __cosmicterm_prompt --on-event fish_prompt
+    printf '\e]133;A\a'
+    set -l dir (string replace -a % %25 -- $PWD | string replace -a ' ' %20)
+    printf '\e]7;file://%s%s\a' $hostname $dir
+
+    # The command starts where fish_prompt ends. It may be defined or replaced
+    # after this file is loaded, so it is wrapped again whenever it changes.
+    if not string match -q '*133;B*' -- (functions fish_prompt)
+        functions -e __cosmicterm_fish_prompt
+        functions -c fish_prompt __cosmicterm_fish_prompt
+        function fish_prompt
+            __cosmicterm_fish_prompt
+            printf '\e]133;B\a'
+        end
+    end
+end
+
+function __cosmicterm_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+
+function __cosmicterm_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
@@ -1,17 +1,33 @@
-use crate::terminal;
+use crate::clipboard::Selection;
+use crate::clipboard::SharedClipboard;
+use crate::color::Rgb;
+use crate::config::Config;
+use crate::grid::Hyperlink;
+use crate::input;
+use crate::input::KeyAction;
+use crate::input::KeyInput;
+use crate::input::KeyboardModes;
+use crate::modes::Mode;
+use crate::mouse;
+use crate::mouse::MouseAction;
+use crate::mouse::MouseProtocol;
+use crate::paste;
+use crate::process::spawn_detached;
+use crate::renderer;
+use crate::renderer::CellMetrics;
+use crate::renderer::PADDING;
+use crate::renderer::RowBuffer;
+use crate::terminal::CellSize;
+use crate::terminal::Progress;
 use crate::terminal::Terminal;
+use crate::terminal::TerminalEvent;
 use crate::window::WindowState;
-use glyphon::Attrs;
-use glyphon::Buffer;
 use glyphon::Color;
-use glyphon::Family;
-use glyphon::Metrics;
 use glyphon::Resolution;
-use glyphon::Shaping;
 use glyphon::TextArea;
 use glyphon::TextBounds;
-use glyphon::Wrap;
-use winit::event_loop::ControlFlow;
+use std::path::PathBuf;
+use std::process::Command;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
@@ -24,36 +40,348 @@ use wgpu::RenderPassDescriptor;
 use wgpu::TextureViewDescriptor;
 use winit::application::ApplicationHandler;
 use winit::dpi::LogicalSize;
+use winit::dpi::PhysicalPosition;
+use winit::dpi::PhysicalSize;
 use winit::event::ElementState;
+use winit::event::MouseButton;
+use winit::event::MouseScrollDelta;
 use winit::event::StartCause;
 use winit::event::WindowEvent;
 use winit::event_loop::ActiveEventLoop;
+use winit::event_loop::ControlFlow;
 use winit::keyboard::Key;
+use winit::keyboard::ModifiersState;
 use winit::keyboard::NamedKey;
+use winit::keyboard::PhysicalKey;
 use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+use winit::window::CursorIcon;
+use winit::window::Fullscreen;
 use winit::window::Window;
 use winit::window::WindowId;
 
 pub struct Application {
     pub window_state: Option<Arc<Mutex<WindowState>>>,
     terminal: Terminal,
+    config: Config,
+    clipboard: SharedClipboard,
+    modifiers: ModifiersState,
+    /// Cell under the mouse pointer
+    mouse_cell: Option<(usize, usize)>,
+    mouse_position: PhysicalPosition<f64>,
+    /// Buttons held down in the order they were pressed, the first is
+    /// reported with the motion
+    mouse_buttons: Vec<mouse::Button>,
+    /// Wheel movement not sent yet, in notches
+    wheel_delta: (f32, f32),
+    /// Link under the mouse pointer, which is underlined
+    hovered_link: Option<Arc<Hyperlink>>,
+    /// Set when the grid has to be drawn again even though the terminal
+    /// didn't change
+    grid_changed: bool,
+    /// Title set by the program, shown after the progress
+    title: String,
+    progress: Progress,
+    /// Sanitized paste waiting to be confirmed
+    pending_paste: Option<String>,
+    /// Keys held down whose press was handled by the terminal, so their
+    /// release isn't sent either
+    shortcut_keys: Vec<PhysicalKey>,
 }
 
 impl Application {
     const APP_NAME: &'static str = "cosmicterm";
-    pub fn new(terminal: Terminal) -> Self {
+    pub fn new(terminal: Terminal, config: Config, clipboard: SharedClipboard) -> Self {
         Self {
             window_state: None,
             terminal,
+            config,
+            clipboard,
+            modifiers: ModifiersState::empty(),
+            mouse_cell: None,
+            mouse_position: PhysicalPosition::new(0.0, 0.0),
+            mouse_buttons: Vec::new(),
+            wheel_delta: (0.0, 0.0),
+            hovered_link: None,
+            grid_changed: false,
+            title: String::new(),
+            progress: Progress::None,
+            pending_paste: None,
+            shortcut_keys: Vec::new(),
+        }
+    }
+
+    /// Carries out the requests of the program running in the terminal
+    fn handle_terminal_events(&mut self) {
+        let Some(state) = self.window_state.clone() else {
+            return;
+        };
+        let state = state.lock().unwrap();
+        let window = &state.window;
+        let scale = window.scale_factor() as f32;
+        for event in self.terminal.events().try_iter() {
+            tracing::debug!("Terminal event: {:?}", event);
+            match event {
+                TerminalEvent::Title(title) => {
+                    self.title = title;
+                    window.set_title(&self.window_title());
+                }
+                TerminalEvent::Progress(progress) => {
+                    self.progress = progress;
+                    window.set_title(&self.window_title());
+                }
+                TerminalEvent::Minimize(minimized) => window.set_minimized(minimized),
+                TerminalEvent::Move { x, y } => {
+                    window.set_outer_position(PhysicalPosition::new(x, y));
+                }
+                TerminalEvent::ResizePixels { width, height } => {
+                    let current = window.inner_size();
+                    let size = |pixels: u16, current: u32| match pixels {
+                        0 => current,
+                        pixels => pixels as u32 + PADDING as u32,
+                    };
+                    let _ = window.request_inner_size(PhysicalSize::new(
+                        size(width, current.width),
+                        size(height, current.height),
+                    ));
+                }
+                TerminalEvent::ResizeCells { cols, rows } => {
+                    let current = window.inner_size();
+                    let size = |cells: u16, cell: f32, current: u32| match cells {
+                        0 => current,
+                        cells => (cells as f32 * cell * scale + PADDING).ceil() as u32,
+                    };
+                    let _ = window.request_inner_size(PhysicalSize::new(
+                        size(cols, state.cell_metrics.width, current.width),
+                        size(rows, state.cell_metrics.height, current.height),
+                    ));
+                }
+                TerminalEvent::Raise => window.focus_window(),
+                TerminalEvent::Maximize(maximized) => window.set_maximized(maximized),
+                TerminalEvent::Fullscreen(fullscreen) => {
+                    let fullscreen = fullscreen.unwrap_or(window.fullscreen().is_none());
+                    window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+                }
+            }
+        }
+    }
+
+    /// Title set by the program, or the name of the application, after the
+    /// percentage of the progress when enabled in the config
+    fn window_title(&self) -> String {
+        let title = match self.title.as_str() {
+            "" => Self::APP_NAME,
+            title => title,
+        };
+        match self.progress.percent() {
+            Some(percent) if self.config.progress_in_title => format!("{}% {}", percent, title),
+            _ => title.to_string(),
+        }
+    }
+}
+
+/// Updates the link under the mouse pointer, returning whether it changed
+fn update_hovered_link(
+    hovered_link: &mut Option<Arc<Hyperlink>>,
+    link: Option<Arc<Hyperlink>>,
+    window: &Window,
+) -> bool {
+    let same = match (hovered_link.as_ref(), link.as_ref()) {
+        (Some(hovered), Some(link)) => Hyperlink::same_link(hovered, link),
+        (hovered, link) => hovered.is_none() && link.is_none(),
+    };
+    if same {
+        return false;
+    }
+    window.set_cursor(if link.is_some() {
+        CursorIcon::Pointer
+    } else {
+        CursorIcon::Default
+    });
+    *hovered_link = link;
+    true
+}
+
+/// Opens a link with the configured opener. The command is run directly,
+/// not through a shell.
+fn open_link(opener: &str, uri: &str) {
+    let mut args = opener.split_whitespace();
+    let Some(program) = args.next() else {
+        return;
+    };
+    // Don't let the URI be taken for an option of the opener
+    if uri.starts_with('-') {
+        tracing::warn!("Not opening link {:?}", uri);
+        return;
+    }
+    tracing::info!("Opening link {} with {}", uri, opener);
+    let mut command = Command::new(program);
+    command.args(args).arg(uri);
+    if let Err(e) = spawn_detached(command) {
+        tracing::warn!("Failed to run link opener {}: {}", program, e);
+    }
+}
+
+/// Runs the action bound to a key, returning whether there was one
+fn handle_shortcut(
+    terminal: &Terminal,
+    clipboard: &SharedClipboard,
+    modifiers: ModifiersState,
+    key: &Key,
+) -> bool {
+    let ctrl_shift = modifiers == ModifiersState::CONTROL | ModifiersState::SHIFT;
+    let shift = modifiers == ModifiersState::SHIFT;
+    match key {
+        // Open a new window in the current directory
+        Key::Character(c) if ctrl_shift && c.eq_ignore_ascii_case("n") => {
+            spawn_window(terminal.current_dir())
+        }
+        // Jump between prompts
+        Key::Named(NamedKey::ArrowUp) if ctrl_shift => terminal.scroll_to_prompt(true),
+        Key::Named(NamedKey::ArrowDown) if ctrl_shift => terminal.scroll_to_prompt(false),
+        // Copy the output of the last command
+        Key::Character(c) if ctrl_shift && c.eq_ignore_ascii_case("o") => {
+            match terminal.last_command_output() {
+                Some(output) => {
+                    let result = clipboard
+                        .lock()
+                        .expect("Failed to lock clipboard")
+                        .set(Selection::Clipboard, output);
+                    if let Err(e) = result {
+                        tracing::warn!("Failed to copy the command output: {}", e);
+                    }
+                }
+                None => tracing::info!("No command output to copy"),
+            }
+        }
+        // Scroll through the scrollback a page at a time, except on the
+        // alternate screen which passes the keys on as xterm does
+        Key::Named(NamedKey::PageUp) if shift && !terminal.alt_screen() => {
+            terminal.scroll_display(terminal.size().rows as isize)
+        }
+        Key::Named(NamedKey::PageDown) if shift && !terminal.alt_screen() => {
+            terminal.scroll_display(-(terminal.size().rows as isize))
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Keeps the release of a key handled by the terminal from being sent to
+/// the program
+fn take_key(shortcut_keys: &mut Vec<PhysicalKey>, key: PhysicalKey) {
+    if !shortcut_keys.contains(&key) {
+        shortcut_keys.push(key);
+    }
+}
+
+/// Lines of scrollback scrolled by a notch of the wheel
+const WHEEL_LINES: f32 = 3.0;
+
+/// Reports a mouse action to the program when it tracks the mouse,
+/// returning whether it does. Shift keeps the mouse for the terminal.
+/// Actions over the padding aren't reported.
+fn report_mouse(
+    terminal: &Terminal,
+    protocol: Option<MouseProtocol>,
+    modifiers: ModifiersState,
+    action: MouseAction,
+    position: Option<((usize, usize), (usize, usize))>,
+) -> bool {
+    let Some(protocol) = protocol.filter(|_| !modifiers.shift_key()) else {
+        return false;
+    };
+    if let Some((cell, pixels)) = position
+        && let Some(report) = protocol.encode(action, modifiers, cell, pixels)
+    {
+        terminal.write(&report);
+    }
+    true
+}
+
+/// Returns the cell under a point of the window, clamped to the grid, and
+/// the point in pixels from the top left corner of the grid. `None` over
+/// the top and left padding.
+fn report_position(
+    terminal: &Terminal,
+    position: PhysicalPosition<f64>,
+    cell: CellMetrics,
+    scale: f32,
+) -> Option<((usize, usize), (usize, usize))> {
+    let (col, row) = renderer::cell_at(position.x, position.y, cell, scale)?;
+    let size = terminal.size();
+    let pixels = (
+        (position.x as f32 - PADDING) as usize,
+        (position.y as f32 - PADDING) as usize,
+    );
+    Some((
+        (
+            col.min(size.cols.saturating_sub(1) as usize),
+            row.min(size.rows.saturating_sub(1) as usize),
+        ),
+        pixels,
+    ))
+}
+
+fn mouse_button(button: MouseButton) -> Option<mouse::Button> {
+    match button {
+        MouseButton::Left => Some(mouse::Button::Left),
+        MouseButton::Middle => Some(mouse::Button::Middle),
+        MouseButton::Right => Some(mouse::Button::Right),
+        _ => None,
+    }
+}
+
+/// Ctrl+Shift+V or Shift+Insert
+fn is_paste_shortcut(modifiers: ModifiersState, key: &Key) -> bool {
+    match key {
+        Key::Character(c) => {
+            modifiers == ModifiersState::CONTROL | ModifiersState::SHIFT
+                && c.eq_ignore_ascii_case("v")
         }
+        Key::Named(NamedKey::Insert) => modifiers == ModifiersState::SHIFT,
+        _ => false,
+    }
+}
+
+/// Sends sanitized text to the program, bracketed if it asked for it
+fn send_paste(terminal: &Terminal, text: &str) {
+    terminal.write(&paste::encode(text, terminal.mode(Mode::BracketedPaste)));
+}
+
+/// Opens another window in a new process, starting in `cwd` when it is
+/// known
+fn spawn_window(cwd: Option<PathBuf>) {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            tracing::warn!("Failed to find the cosmicterm executable: {}", e);
+            return;
+        }
+    };
+    let mut command = Command::new(exe);
+    if let Some(cwd) = cwd.filter(|cwd| cwd.is_dir()) {
+        command.arg("--working-directory").arg(cwd);
+    }
+    if let Err(e) = spawn_detached(command) {
+        tracing::warn!("Failed to open a new window: {}", e);
+    }
+}
+
+fn clear_color(color: Rgb) -> wgpu::Color {
+    let [r, g, b, a] = color.to_linear(1.0);
+    wgpu::Color {
+        r: r as f64,
+        g: g as f64,
+        b: b as f64,
+        a: a as f64,
     }
 }
 
 impl ApplicationHandler for Application {
     fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        self.handle_terminal_events();
         match cause {
-            StartCause::Init { .. }
-            | StartCause::ResumeTimeReached { .. } => {
+            StartCause::Init | StartCause::ResumeTimeReached { .. } => {
                 if let Some(state) = &self.window_state {
                     let window = &state.lock().unwrap().window;
                     window.request_redraw();
@@ -105,7 +433,9 @@ impl ApplicationHandler for Application {
             viewport,
             atlas,
             text_renderer,
-            text_buffer,
+            quad_renderer,
+            row_buffers,
+            cell_metrics,
             ..
         } = &mut *state;
 
@@ -118,35 +448,15 @@ impl ApplicationHandler for Application {
                 // reconfigure your surface
                 surface_config.width = phys_w;
                 surface_config.height = phys_h;
-                surface.configure(&device, &surface_config);
+                surface.configure(device, surface_config);
 
                 // 1) compute cols/rows in logical space
                 let scale = window.scale_factor() as f32;
-                let log_w = (phys_w - 10) as f32 / scale;
-                let log_h = (phys_h - 10) as f32 / scale;
-
-                let metrics = Metrics::new(16.0, 16.0 * 1.2);
-                let mut buffer = Buffer::new_empty(metrics);
-
-                let (cell_width, cell_height) = {
-                    buffer.set_wrap(font_system, Wrap::None);
-
-                    // Use size of space to determine cell size
-                    buffer.set_text(
-                        font_system,
-                        " ",
-                        &Attrs::new().family(Family::Monospace),
-                        Shaping::Advanced,
-                    );
-                    let layout = buffer.line_layout(font_system, 0).unwrap();
-                    let w = layout[0].w;
-                    buffer.set_monospace_width(font_system, Some(w));
-                    (w, metrics.line_height)
-                };
+                let log_w = (phys_w as f32 - PADDING).max(0.0) / scale;
+                let log_h = (phys_h as f32 - PADDING).max(0.0) / scale;
 
-                let cols = (log_w / cell_width).floor() as u16;
-                let rows = (log_h / cell_height).floor() as u16;
-                tracing::info!("Resizing terminal to {} cols and {} rows", cols, rows);
+                let cols = (log_w / cell_metrics.width).floor() as u16;
+                let rows = (log_h / cell_metrics.height).floor() as u16;
 
                 tracing::info!(
                     "phys = {}×{}px, logical = {}×{}px, cols×rows = {}×{}",
@@ -158,29 +468,71 @@ impl ApplicationHandler for Application {
                     rows,
                 );
 
-                // 2) tell Glyphon the *physical* viewport size
-                text_buffer.set_size(font_system, Some(phys_w as f32), Some(phys_h as f32));
-
-                // 3) resize your TTY
-                self.terminal.resize(cols, rows).unwrap();
+                // 2) resize your TTY
+                let cell = CellSize {
+                    width: (cell_metrics.width * scale).round() as u16,
+                    height: (cell_metrics.height * scale).round() as u16,
+                };
+                self.terminal.resize(cols, rows, cell).unwrap();
                 window.request_redraw();
             }
             WindowEvent::RedrawRequested => {
                 viewport.update(
-                    &queue,
+                    queue,
                     Resolution {
                         width: surface_config.width,
                         height: surface_config.height,
                     },
                 );
 
-                if self.terminal.is_dirty() {
-                    text_buffer.set_text(
-                        font_system,
-                        &self.terminal.as_text(),
-                        &Attrs::new().family(Family::Monospace),
-                        Shaping::Advanced,
+                let reverse_video = self.terminal.mode(Mode::ReverseVideo);
+                let (default_fg, default_bg) = renderer::default_colors(reverse_video);
+                if self.terminal.is_dirty() || self.grid_changed {
+                    let mut rows = self.terminal.visible_rows();
+                    // The text under the mouse pointer may have changed
+                    let link = self
+                        .mouse_cell
+                        .and_then(|(x, y)| self.terminal.hyperlink_at(x, y));
+                    update_hovered_link(&mut self.hovered_link, link, window);
+                    // DECTCEM hides the cursor
+                    let mut cursor = self.terminal.cursor();
+                    if let Some(text) = &self.pending_paste {
+                        paste::draw_confirmation(&mut rows, text);
+                        cursor = None;
+                    }
+                    let scale = window.scale_factor() as f32;
+
+                    row_buffers.truncate(rows.len());
+                    while row_buffers.len() < rows.len() {
+                        row_buffers.push(RowBuffer::new(font_system));
+                    }
+                    for (y, (buffer, row)) in row_buffers.iter_mut().zip(&rows).enumerate() {
+                        let cursor_x = cursor.filter(|cursor| cursor.1 == y).map(|cursor| cursor.0);
+                        buffer.update(font_system, row, cursor_x, reverse_video);
+                    }
+
+                    let mut quads = renderer::grid_quads(
+                        &rows,
+                        cursor,
+                        reverse_video,
+                        self.hovered_link.as_ref(),
+                        *cell_metrics,
+                        scale,
                     );
+                    quads.extend(renderer::progress_quad(
+                        self.terminal.progress(),
+                        surface_config.width as f32,
+                        scale,
+                    ));
+                    quad_renderer.prepare(
+                        device,
+                        queue,
+                        &quads,
+                        surface_config.width,
+                        surface_config.height,
+                    );
+
+                    let line_height = cell_metrics.height * scale;
                     text_renderer
                         .prepare(
                             device,
@@ -188,19 +540,20 @@ impl ApplicationHandler for Application {
                             font_system,
                             atlas,
                             viewport,
-                            [TextArea {
-                                buffer: text_buffer,
-                                left: 10.0,
-                                top: 10.0,
-                                scale: window.scale_factor() as f32,
+                            row_buffers.iter().enumerate().map(|(y, row)| TextArea {
+                                buffer: &row.buffer,
+                                left: PADDING,
+                                top: PADDING + y as f32 * line_height,
+                                scale,
                                 bounds: TextBounds::default(),
-                                default_color: Color::rgb(255, 255, 255),
+                                default_color: Color::rgb(default_fg.r, default_fg.g, default_fg.b),
                                 custom_glyphs: &[],
-                            }],
+                            }),
                             swash_cache,
                         )
                         .unwrap();
                     self.terminal.clear_dirty();
+                    self.grid_changed = false;
                 }
 
                 let frame = surface.get_current_texture().unwrap();
@@ -215,7 +568,7 @@ impl ApplicationHandler for Application {
                             view: &view,
                             resolve_target: None,
                             ops: Operations {
-                                load: LoadOp::Clear(wgpu::Color::BLACK),
+                                load: LoadOp::Clear(clear_color(default_bg)),
                                 store: wgpu::StoreOp::Store,
                             },
                         })],
@@ -231,7 +584,8 @@ impl ApplicationHandler for Application {
                     // (optional) also ensure the scissor covers the full buffer:
                     pass.set_scissor_rect(0, 0, w as u32, h as u32);
 
-                    text_renderer.render(&atlas, &viewport, &mut pass).unwrap();
+                    quad_renderer.render(&mut pass);
+                    text_renderer.render(atlas, viewport, &mut pass).unwrap();
                 }
 
                 queue.submit(Some(encoder.finish()));
@@ -243,34 +597,226 @@ impl ApplicationHandler for Application {
                 atlas.trim();
             }
             WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::CursorMoved { position, .. } => {
+                let scale = window.scale_factor() as f32;
+                self.mouse_cell = renderer::cell_at(position.x, position.y, *cell_metrics, scale);
+                let link = self
+                    .mouse_cell
+                    .and_then(|(x, y)| self.terminal.hyperlink_at(x, y));
+                if update_hovered_link(&mut self.hovered_link, link, window) {
+                    self.grid_changed = true;
+                    window.request_redraw();
+                }
+
+                // Motion is reported when the cell changes, or the pixel
+                // with SGR-Pixels
+                let previous =
+                    report_position(&self.terminal, self.mouse_position, *cell_metrics, scale);
+                let current = report_position(&self.terminal, position, *cell_metrics, scale);
+                self.mouse_position = position;
+                let protocol = self.terminal.mouse_protocol();
+                let moved = match protocol {
+                    Some(protocol) if protocol.encoding == mouse::Encoding::SgrPixels => {
+                        current != previous
+                    }
+                    _ => current.map(|(cell, _)| cell) != previous.map(|(cell, _)| cell),
+                };
+                if moved {
+                    let action = MouseAction::Motion(self.mouse_buttons.first().copied());
+                    report_mouse(&self.terminal, protocol, self.modifiers, action, current);
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.mouse_cell = None;
+                if update_hovered_link(&mut self.hovered_link, None, window) {
+                    self.grid_changed = true;
+                    window.request_redraw();
+                }
+            }
+            WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => {
+                let Some(button) = mouse_button(button) else {
+                    return;
+                };
+                let pressed = button_state == ElementState::Pressed;
+                self.mouse_buttons.retain(|&held| held != button);
+                let action = if pressed {
+                    self.mouse_buttons.push(button);
+                    MouseAction::Press(button)
+                } else {
+                    MouseAction::Release(button)
+                };
+                let scale = window.scale_factor() as f32;
+                let position =
+                    report_position(&self.terminal, self.mouse_position, *cell_metrics, scale);
+                let protocol = self.terminal.mouse_protocol();
+                if report_mouse(&self.terminal, protocol, self.modifiers, action, position) {
+                    return;
+                }
+                // Ctrl+click opens links
+                if action == MouseAction::Press(mouse::Button::Left)
+                    && self.modifiers.control_key()
+                    && let Some(link) = &self.hovered_link
+                {
+                    open_link(&self.config.link_opener, &link.uri);
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let scale = window.scale_factor() as f32;
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x, y),
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        let notch = cell_metrics.height * scale * WHEEL_LINES;
+                        (delta.x as f32 / notch, delta.y as f32 / notch)
+                    }
+                };
+                self.wheel_delta.0 += x;
+                self.wheel_delta.1 += y;
+                let notches = (self.wheel_delta.0.trunc(), self.wheel_delta.1.trunc());
+                self.wheel_delta.0 -= notches.0;
+                self.wheel_delta.1 -= notches.1;
+
+                // Positive deltas reveal the content above and on the left
+                let buttons = [
+                    (notches.1, mouse::Button::WheelUp, mouse::Button::WheelDown),
+                    (
+                        notches.0,
+                        mouse::Button::WheelLeft,
+                        mouse::Button::WheelRight,
+                    ),
+                ];
+                let position =
+                    report_position(&self.terminal, self.mouse_position, *cell_metrics, scale);
+                let protocol = self.terminal.mouse_protocol();
+                let mut reported = false;
+                for (notches, positive, negative) in buttons {
+                    let button = if notches > 0.0 { positive } else { negative };
+                    for _ in 0..notches.abs() as usize {
+                        let action = MouseAction::Press(button);
+                        reported |= report_mouse(
+                            &self.terminal,
+                            protocol,
+                            self.modifiers,
+                            action,
+                            position,
+                        );
+                    }
+                }
+                if !reported && notches.1 != 0.0 {
+                    self.terminal
+                        .scroll_display((notches.1 * WHEEL_LINES) as isize);
+                    window.request_redraw();
+                }
+            }
+            // Focus reporting
+            WindowEvent::Focused(focused) => self.terminal.set_focused(focused),
             WindowEvent::KeyboardInput {
                 device_id: _,
                 event,
                 is_synthetic,
             } => {
-                if is_synthetic || event.state == ElementState::Released {
+                if is_synthetic {
                     return;
                 }
                 tracing::info!("Keyboard input: {:?}", event);
+                // Releases are only sent with the kitty keyboard protocol
+                let pressed = event.state == ElementState::Pressed;
 
-                if let Key::Named(NamedKey::Escape) = event.key_without_modifiers() {
-                    tracing::info!("Terminal text: {}", self.terminal.as_text());
+                // Keys the terminal took on press have their release
+                // swallowed too, even once the modifiers are let go
+                if !pressed
+                    && let Some(i) = self
+                        .shortcut_keys
+                        .iter()
+                        .position(|&key| key == event.physical_key)
+                {
+                    self.shortcut_keys.swap_remove(i);
                     return;
                 }
 
-                if let Some(text) = event.text_with_all_modifiers() {
-                    tracing::info!("Text input: {:?}", text);
-                    self.terminal.write(text.as_bytes());
-                } else {
-                    let key = event.key_without_modifiers();
-                    let data: &[u8] = match key {
-                        Key::Named(NamedKey::ArrowUp) => b"\x1B[A",
-                        Key::Named(NamedKey::ArrowDown) => b"\x1B[B",
-                        Key::Named(NamedKey::ArrowRight) => b"\x1B[C",
-                        Key::Named(NamedKey::ArrowLeft) => b"\x1B[D",
-                        _ => return,
-                    };
-                    self.terminal.write(data);
+                // A paste waiting to be confirmed takes all the keys
+                if self.pending_paste.is_some() {
+                    if pressed && let Some(text) = self.pending_paste.take() {
+                        take_key(&mut self.shortcut_keys, event.physical_key);
+                        match event.key_without_modifiers() {
+                            Key::Named(NamedKey::Enter) => send_paste(&self.terminal, &text),
+                            Key::Named(NamedKey::Escape) => tracing::info!("Paste cancelled"),
+                            _ => self.pending_paste = Some(text),
+                        }
+                        self.grid_changed = true;
+                        window.request_redraw();
+                    }
+                    return;
+                }
+
+                if pressed && is_paste_shortcut(self.modifiers, &event.key_without_modifiers()) {
+                    take_key(&mut self.shortcut_keys, event.physical_key);
+                    let text = self
+                        .clipboard
+                        .lock()
+                        .expect("Failed to lock clipboard")
+                        .get(Selection::Clipboard);
+                    match text {
+                        Ok(text) if text.is_empty() => {}
+                        Ok(text) => {
+                            let text = paste::sanitize(&text);
+                            // Shells in bracketed paste mode don't run what
+                            // is pasted until Enter is pressed
+                            if !self.terminal.mode(Mode::BracketedPaste)
+                                && paste::needs_confirmation(&text)
+                            {
+                                self.pending_paste = Some(text);
+                                self.grid_changed = true;
+                                window.request_redraw();
+                            } else {
+                                send_paste(&self.terminal, &text);
+                            }
+                        }
+                        Err(e) => tracing::warn!("Failed to read the clipboard: {}", e),
+                    }
+                    return;
+                }
+
+                let key = event.key_without_modifiers();
+                if pressed && handle_shortcut(&self.terminal, &self.clipboard, self.modifiers, &key)
+                {
+                    take_key(&mut self.shortcut_keys, event.physical_key);
+                    return;
+                }
+
+                let action = match event.state {
+                    ElementState::Pressed if event.repeat => KeyAction::Repeat,
+                    ElementState::Pressed => KeyAction::Press,
+                    ElementState::Released => KeyAction::Release,
+                };
+                let base_layout_key = match event.physical_key {
+                    PhysicalKey::Code(code) => input::base_layout_key(code),
+                    PhysicalKey::Unidentified(_) => None,
+                };
+                let input = KeyInput {
+                    key: &key,
+                    shifted_key: &event.logical_key,
+                    base_layout_key,
+                    location: event.location,
+                    action,
+                    text: event.text_with_all_modifiers(),
+                    modifiers: self.modifiers,
+                };
+                let modes = KeyboardModes {
+                    cursor_keys: self.terminal.mode(Mode::CursorKeys),
+                    keypad_application: self.terminal.mode(Mode::KeypadApplication),
+                    line_feed_new_line: self.terminal.mode(Mode::LineFeedNewLine),
+                    backspace: self.config.backspace,
+                    delete: self.config.delete,
+                    keyboard_flags: self.terminal.keyboard_flags(),
+                    modify_other_keys: self.terminal.modify_other_keys(),
+                };
+                if let Some(bytes) = input::encode(&input, &modes) {
+                    self.terminal.write(&bytes);
                 }
             }
             _ => {}
@@ -0,0 +1,126 @@
+/// A 94-character set that can be designated into G0–G3
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    /// Same as ASCII, with `#` replaced by the pound sign
+    Uk,
+    /// Line drawing characters used by ncurses and dialog
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    /// Returns the set named by the final byte of a designation sequence
+    pub fn from_designator(byte: u8) -> Option<Self> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'A' => Some(Charset::Uk),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    pub fn map(self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::Uk => match c {
+                '#' => '£',
+                _ => c,
+            },
+            Charset::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c,
+            },
+        }
+    }
+}
+
+/// The G0–G3 sets and which of them is used to print characters
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Charsets {
+    sets: [Charset; 4],
+    /// Set invoked into GL by SO, SI, LS2 and LS3
+    active: usize,
+    /// Set used for the next character only, invoked by SS2 and SS3
+    single_shift: Option<usize>,
+}
+
+impl Charsets {
+    pub fn designate(&mut self, index: usize, charset: Charset) {
+        self.sets[index] = charset;
+    }
+
+    /// Locking shift, which lasts until the next one
+    pub fn invoke(&mut self, index: usize) {
+        self.active = index;
+    }
+
+    pub fn single_shift(&mut self, index: usize) {
+        self.single_shift = Some(index);
+    }
+
+    /// Translates a printed character, consuming any single shift
+    pub fn map(&mut self, c: char) -> char {
+        let index = self.single_shift.take().unwrap_or(self.active);
+        // Only the 94 graphic characters of GL are translated
+        if c.is_ascii_graphic() {
+            self.sets[index].map(c)
+        } else {
+            c
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts() {
+        let mut charsets = Charsets::default();
+        charsets.designate(1, Charset::DecSpecialGraphics);
+        charsets.designate(2, Charset::Uk);
+        assert_eq!(charsets.map('q'), 'q');
+        charsets.invoke(1);
+        assert_eq!(charsets.map('q'), '─');
+        // Only graphic characters are translated
+        assert_eq!(charsets.map(' '), ' ');
+        assert_eq!(charsets.map('é'), 'é');
+        // A single shift lasts for one character
+        charsets.single_shift(2);
+        assert_eq!(charsets.map('#'), '£');
+        assert_eq!(charsets.map('#'), '#');
+        assert_eq!(charsets.map('x'), '│');
+    }
+}
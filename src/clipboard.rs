@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Clipboard shared by the terminal and the application
+pub type SharedClipboard = Arc<Mutex<dyn Clipboard>>;
+
+/// Which selection a clipboard operation applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Clipboard,
+    /// The X11 primary selection. Platforms without one use the clipboard.
+    Primary,
+}
+
+pub trait Clipboard: Send {
+    fn get(&mut self, selection: Selection) -> Result<String>;
+    fn set(&mut self, selection: Selection, text: String) -> Result<()>;
+}
+
+/// The clipboard of the desktop
+pub struct SystemClipboard {
+    clipboard: arboard::Clipboard,
+}
+
+impl SystemClipboard {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            clipboard: arboard::Clipboard::new()?,
+        })
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Clipboard for SystemClipboard {
+    fn get(&mut self, selection: Selection) -> Result<String> {
+        use arboard::GetExtLinux;
+        Ok(self
+            .clipboard
+            .get()
+            .clipboard(linux_clipboard_kind(selection))
+            .text()?)
+    }
+
+    fn set(&mut self, selection: Selection, text: String) -> Result<()> {
+        use arboard::SetExtLinux;
+        Ok(self
+            .clipboard
+            .set()
+            .clipboard(linux_clipboard_kind(selection))
+            .text(text)?)
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn linux_clipboard_kind(selection: Selection) -> arboard::LinuxClipboardKind {
+    match selection {
+        Selection::Clipboard => arboard::LinuxClipboardKind::Clipboard,
+        Selection::Primary => arboard::LinuxClipboardKind::Primary,
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+impl Clipboard for SystemClipboard {
+    fn get(&mut self, _selection: Selection) -> Result<String> {
+        Ok(self.clipboard.get_text()?)
+    }
+
+    fn set(&mut self, _selection: Selection, text: String) -> Result<()> {
+        Ok(self.clipboard.set_text(text)?)
+    }
+}
+
+/// A clipboard that only lives in memory, used when there is no display
+/// server to talk to, and in tests
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    clipboard: String,
+    primary: String,
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get(&mut self, selection: Selection) -> Result<String> {
+        Ok(match selection {
+            Selection::Clipboard => self.clipboard.clone(),
+            Selection::Primary => self.primary.clone(),
+        })
+    }
+
+    fn set(&mut self, selection: Selection, text: String) -> Result<()> {
+        match selection {
+            Selection::Clipboard => self.clipboard = text,
+            Selection::Primary => self.primary = text,
+        }
+        Ok(())
+    }
+}
+
+/// Returns the system clipboard, or a clipboard in memory when it isn't
+/// available
+pub fn system_or_memory() -> SharedClipboard {
+    match SystemClipboard::new() {
+        Ok(clipboard) => Arc::new(Mutex::new(clipboard)),
+        Err(e) => {
+            tracing::warn!("System clipboard unavailable, using one in memory: {}", e);
+            Arc::new(Mutex::new(MemoryClipboard::default()))
+        }
+    }
+}
@@ -0,0 +1,116 @@
+/// An 8-bit sRGB color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Mixes `self` with `other`, `amount` being the weight of `other`
+    pub fn blend(self, other: Rgb, amount: f32) -> Rgb {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+        Rgb::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+
+    /// Converts to linear RGBA, as expected by sRGB render targets
+    pub fn to_linear(self, alpha: f32) -> [f32; 4] {
+        let linear = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        [linear(self.r), linear(self.g), linear(self.b), alpha]
+    }
+}
+
+/// A color as set by SGR
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Color {
+    /// The default foreground or background color
+    #[default]
+    Default,
+    /// An entry of the 256-color palette. The first 16 are the ANSI colors.
+    Indexed(u8),
+    Rgb(Rgb),
+}
+
+impl Color {
+    pub fn resolve(self, default: Rgb) -> Rgb {
+        match self {
+            Color::Default => default,
+            Color::Indexed(index) => indexed(index),
+            Color::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+pub const DEFAULT_FOREGROUND: Rgb = Rgb::new(255, 255, 255);
+pub const DEFAULT_BACKGROUND: Rgb = Rgb::new(0, 0, 0);
+
+const ANSI: [Rgb; 16] = [
+    Rgb::new(0, 0, 0),
+    Rgb::new(205, 49, 49),
+    Rgb::new(13, 188, 121),
+    Rgb::new(229, 229, 16),
+    Rgb::new(36, 114, 200),
+    Rgb::new(188, 63, 188),
+    Rgb::new(17, 168, 205),
+    Rgb::new(229, 229, 229),
+    Rgb::new(102, 102, 102),
+    Rgb::new(241, 76, 76),
+    Rgb::new(35, 209, 139),
+    Rgb::new(245, 245, 67),
+    Rgb::new(59, 142, 234),
+    Rgb::new(214, 112, 214),
+    Rgb::new(41, 184, 219),
+    Rgb::new(255, 255, 255),
+];
+
+/// Returns the color of the xterm 256-color palette at `index`
+pub fn indexed(index: u8) -> Rgb {
+    match index {
+        0..16 => ANSI[index as usize],
+        // 6x6x6 color cube
+        16..232 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            Rgb::new(level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        // Grayscale ramp
+        232.. => {
+            let level = 8 + (index - 232) * 10;
+            Rgb::new(level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette() {
+        assert_eq!(indexed(1), ANSI[1]);
+        assert_eq!(indexed(16), Rgb::new(0, 0, 0));
+        assert_eq!(indexed(196), Rgb::new(255, 0, 0));
+        assert_eq!(indexed(110), Rgb::new(135, 175, 215));
+        assert_eq!(indexed(232), Rgb::new(8, 8, 8));
+        assert_eq!(indexed(255), Rgb::new(238, 238, 238));
+        assert_eq!(
+            Color::Default.resolve(DEFAULT_BACKGROUND),
+            DEFAULT_BACKGROUND
+        );
+    }
+}
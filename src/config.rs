@@ -0,0 +1,101 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// User settings, read from `config.toml` in the config directory
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Whether programs may move, resize or iconify the window with CSI t
+    pub allow_window_ops: bool,
+    /// Command that opens hyperlinks on Ctrl+click, given the URI as its
+    /// last argument
+    pub link_opener: String,
+    /// Whether programs may set the clipboard with OSC 52
+    pub allow_clipboard_write: bool,
+    /// Whether programs may read the clipboard with OSC 52
+    pub allow_clipboard_read: bool,
+    /// Largest text programs may put in the clipboard, in bytes
+    pub clipboard_max_size: usize,
+    /// Whether bash, zsh and fish load scripts that mark prompts and report
+    /// the current directory
+    pub shell_integration: bool,
+    /// Command that shows desktop notifications sent with OSC 9, 99 and
+    /// 777, given the title and the body as its last two arguments, after
+    /// `--`. Empty to ignore them.
+    pub notify_command: String,
+    /// Whether notifications are dropped while the window has the focus
+    pub suppress_focused_notifications: bool,
+    /// Whether the progress reported with OSC 9;4 is shown as a percentage
+    /// before the window title
+    pub progress_in_title: bool,
+    /// What the Backspace key sends
+    pub backspace: EraseKey,
+    /// What the Delete key sends
+    pub delete: EraseKey,
+}
+
+/// Bytes sent by the Backspace and Delete keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EraseKey {
+    /// DEL (0x7f)
+    Del,
+    /// BS (0x08), also known as ^H
+    CtrlH,
+    /// `CSI 3 ~`, the VT220 Remove key
+    DeleteSequence,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            allow_window_ops: false,
+            link_opener: "xdg-open".to_string(),
+            allow_clipboard_write: true,
+            allow_clipboard_read: false,
+            clipboard_max_size: 1024 * 1024,
+            shell_integration: true,
+            notify_command: "notify-send".to_string(),
+            suppress_focused_notifications: true,
+            progress_in_title: true,
+            backspace: EraseKey::Del,
+            delete: EraseKey::DeleteSequence,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults when it is missing
+    /// or invalid
+    pub fn load() -> Self {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Self::default();
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::debug!("Not reading config file {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        match toml::from_str(&text) {
+            Ok(config) => {
+                tracing::info!("Loaded config from {}", path.display());
+                config
+            }
+            Err(e) => {
+                tracing::warn!("Invalid config file {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/cosmicterm`, or `~/.config/cosmicterm`
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("cosmicterm"))
+}
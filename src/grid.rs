@@ -0,0 +1,649 @@
+use crate::color::Color;
+use crate::terminal::Size;
+use bitflags::bitflags;
+use std::collections::VecDeque;
+use std::ops::Index;
+use std::ops::IndexMut;
+use std::ops::Range;
+use std::sync::Arc;
+
+bitflags! {
+    /// Rendition flags set through SGR
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Flags: u16 {
+        const BOLD = 1 << 0;
+        const DIM = 1 << 1;
+        const ITALIC = 1 << 2;
+        const UNDERLINE = 1 << 3;
+        const INVERSE = 1 << 4;
+        const HIDDEN = 1 << 5;
+        const STRIKEOUT = 1 << 6;
+        const DOUBLE_UNDERLINE = 1 << 7;
+    }
+}
+
+bitflags! {
+    /// Semantic prompt marks set by OSC 133 on the line the cursor was on
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Marks: u8 {
+        /// Start of the prompt
+        const PROMPT = 1 << 0;
+        /// Start of the command typed by the user
+        const INPUT = 1 << 1;
+        /// Start of the output of the command
+        const OUTPUT = 1 << 2;
+        /// End of the command, with its exit code
+        const COMMAND_END = 1 << 3;
+    }
+}
+
+/// A link set by OSC 8. Cells with the same id and URI belong to the same
+/// link, even when they aren't next to each other. Without an id, only the
+/// cells printed while the link was set belong to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hyperlink {
+    pub id: Option<String>,
+    pub uri: String,
+}
+
+impl Hyperlink {
+    /// Whether the cells with these links belong to the same one
+    pub fn same_link(a: &Arc<Hyperlink>, b: &Arc<Hyperlink>) -> bool {
+        Arc::ptr_eq(a, b) || (a.id.is_some() && a == b)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+    pub hyperlink: Option<Arc<Hyperlink>>,
+}
+
+/// A single character cell on the screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub attrs: Attributes,
+    /// Number of columns taken by `c`. Wide characters are followed by a
+    /// spacer cell with a width of 0.
+    pub width: u8,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            attrs: Attributes::default(),
+            width: 1,
+        }
+    }
+}
+
+impl Cell {
+    pub fn blank(attrs: Attributes) -> Self {
+        Self {
+            attrs,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_spacer(&self) -> bool {
+        self.width == 0
+    }
+}
+
+/// A line of cells, either on screen or in the scrollback
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    cells: Vec<Cell>,
+    /// Set when the line was continued on the next one by autowrap, so the
+    /// two can be joined back together
+    wrapped: bool,
+    marks: Marks,
+    /// Column of the first mark of each kind, by bit
+    mark_columns: [usize; 4],
+    /// Exit code of the command that ended on this line
+    exit_code: Option<i32>,
+}
+
+impl Row {
+    pub fn new(cols: usize, template: &Cell) -> Self {
+        Self {
+            cells: vec![template.clone(); cols],
+            wrapped: false,
+            marks: Marks::empty(),
+            mark_columns: [0; 4],
+            exit_code: None,
+        }
+    }
+
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    pub fn marks(&self) -> Marks {
+        self.marks
+    }
+
+    /// Adds a single mark at column `x`, unless the line already has it
+    pub fn add_mark(&mut self, mark: Marks, x: usize) {
+        if !self.marks.contains(mark) {
+            self.mark_columns[mark.bits().trailing_zeros() as usize] = x;
+            self.marks.insert(mark);
+        }
+    }
+
+    /// Column of a single mark, if the line has it
+    pub fn mark_column(&self, mark: Marks) -> Option<usize> {
+        self.marks
+            .contains(mark)
+            .then(|| self.mark_columns[mark.bits().trailing_zeros() as usize])
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn set_exit_code(&mut self, exit_code: Option<i32>) {
+        self.exit_code = exit_code;
+    }
+
+    pub fn set_wrapped(&mut self, wrapped: bool) {
+        self.wrapped = wrapped;
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::default());
+        // Don't leave half of a wide character at the end of the line
+        if let Some(last) = self.cells.last_mut()
+            && last.width > 1
+        {
+            *last = Cell::blank(std::mem::take(&mut last.attrs));
+        }
+    }
+
+    /// Blanks the whole line, dropping its marks
+    pub fn reset(&mut self, template: &Cell) {
+        self.erase(0..self.cells.len(), template);
+        self.marks = Marks::empty();
+        self.exit_code = None;
+    }
+
+    /// Blanks the cells in `range`, including the halves of any wide
+    /// character cut by its bounds. Erasing up to the end of the line also
+    /// drops its wrapped flag.
+    pub fn erase(&mut self, range: Range<usize>, template: &Cell) {
+        let end = range.end.min(self.cells.len());
+        let start = range.start.min(end);
+        if end == self.cells.len() {
+            self.wrapped = false;
+        }
+        if start == end {
+            return;
+        }
+        self.clear_wide(start);
+        self.clear_wide(end - 1);
+        for cell in &mut self.cells[start..end] {
+            *cell = template.clone();
+        }
+    }
+
+    /// Inserts `count` blank cells at `x`, shifting the cells up to `end` to
+    /// the right. Cells shifted past `end` are lost.
+    pub fn insert_cells(&mut self, x: usize, count: usize, end: usize, template: &Cell) {
+        let end = end.min(self.cells.len());
+        if x >= end {
+            return;
+        }
+        let count = count.min(end - x);
+        self.clear_wide(x);
+        self.clear_wide(end - 1);
+        self.cells[x..end].rotate_right(count);
+        for cell in &mut self.cells[x..x + count] {
+            *cell = template.clone();
+        }
+        // A wide character may have lost its spacer past the end
+        if self.cells[end - 1].width > 1 {
+            self.clear_wide(end - 1);
+        }
+    }
+
+    /// Deletes `count` cells at `x`, shifting the cells up to `end` to the
+    /// left and filling the gap with blank cells
+    pub fn delete_cells(&mut self, x: usize, count: usize, end: usize, template: &Cell) {
+        let end = end.min(self.cells.len());
+        if x >= end {
+            return;
+        }
+        let count = count.min(end - x);
+        self.clear_wide(x);
+        self.clear_wide(x + count - 1);
+        self.clear_wide(end - 1);
+        self.cells[x..end].rotate_left(count);
+        for cell in &mut self.cells[end - count..end] {
+            *cell = template.clone();
+        }
+    }
+
+    /// Blanks both halves of the wide character at `x`, if there is one
+    pub fn clear_wide(&mut self, x: usize) {
+        let Some(cell) = self.cells.get(x) else {
+            return;
+        };
+        let (start, end) = if cell.is_spacer() && x > 0 {
+            (x - 1, x + 1)
+        } else if cell.width > 1 {
+            (x, x + 2)
+        } else {
+            return;
+        };
+        for x in start..end.min(self.cells.len()) {
+            let attrs = std::mem::take(&mut self.cells[x].attrs);
+            self.cells[x] = Cell::blank(attrs);
+        }
+    }
+
+    /// Returns the text of the row without the trailing blanks
+    pub fn text(&self) -> String {
+        self.full_text().trim_end().to_string()
+    }
+
+    /// Returns the text of every cell of the row, which matters for lines
+    /// that wrapped on a blank
+    pub fn full_text(&self) -> String {
+        self.text_in(0..self.cells.len())
+    }
+
+    /// Returns the text of the cells in `cols`
+    pub fn text_in(&self, cols: Range<usize>) -> String {
+        let end = cols.end.min(self.cells.len());
+        self.cells[cols.start.min(end)..end]
+            .iter()
+            .filter(|cell| !cell.is_spacer())
+            .map(|cell| cell.c)
+            .collect()
+    }
+}
+
+impl Index<usize> for Row {
+    type Output = Cell;
+
+    fn index(&self, index: usize) -> &Cell {
+        &self.cells[index]
+    }
+}
+
+impl IndexMut<usize> for Row {
+    fn index_mut(&mut self, index: usize) -> &mut Cell {
+        &mut self.cells[index]
+    }
+}
+
+/// Ring buffer of the lines that scrolled off the top of the screen
+pub struct Scrollback {
+    rows: VecDeque<Row>,
+    max_rows: usize,
+}
+
+impl Scrollback {
+    pub fn new(max_rows: usize) -> Self {
+        Self {
+            rows: VecDeque::new(),
+            max_rows,
+        }
+    }
+
+    pub fn push(&mut self, row: Row) {
+        if self.max_rows == 0 {
+            return;
+        }
+        if self.rows.len() == self.max_rows {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+    }
+
+    /// Takes back the most recent line, used when the screen grows
+    pub fn pop(&mut self) -> Option<Row> {
+        self.rows.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+}
+
+/// The visible screen plus its scrollback. All coordinates are relative to
+/// the top-left corner of the screen, except for lines, which are counted
+/// from the oldest line of the scrollback. Those numbers change as lines are
+/// dropped from a full scrollback, so they are never kept, and the view is
+/// kept as an offset from the screen instead.
+pub struct Grid {
+    rows: Vec<Row>,
+    scrollback: Scrollback,
+    size: Size,
+    /// Number of lines the view is scrolled back into the scrollback
+    display_offset: usize,
+}
+
+impl Grid {
+    pub fn new(size: Size, max_scrollback: usize) -> Self {
+        let template = Cell::default();
+        Self {
+            rows: (0..size.rows)
+                .map(|_| Row::new(size.cols as usize, &template))
+                .collect(),
+            scrollback: Scrollback::new(max_scrollback),
+            size,
+            display_offset: 0,
+        }
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut Row {
+        &mut self.rows[y]
+    }
+
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+        self.display_offset = 0;
+    }
+
+    /// Number of lines in the scrollback and on screen
+    pub fn total_lines(&self) -> usize {
+        self.scrollback.len() + self.rows.len()
+    }
+
+    /// Returns a line of the scrollback or of the screen
+    pub fn line(&self, line: usize) -> &Row {
+        match line.checked_sub(self.scrollback.len()) {
+            Some(y) => &self.rows[y],
+            None => &self.scrollback.rows[line],
+        }
+    }
+
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+
+    /// Scrolls the view, 0 showing the screen and the length of the
+    /// scrollback its oldest lines
+    pub fn set_display_offset(&mut self, offset: usize) {
+        self.display_offset = offset.min(self.scrollback.len());
+    }
+
+    /// Line shown at the top of the view
+    pub fn display_top(&self) -> usize {
+        self.scrollback.len() - self.display_offset
+    }
+
+    /// Returns a line of the view
+    pub fn visible_row(&self, y: usize) -> Option<&Row> {
+        let line = self.display_top() + y;
+        (y < self.rows.len()).then(|| self.line(line))
+    }
+
+    /// Keeps a line scrolled off the top of the screen. The view stays on the
+    /// same lines while scrolled back, even when the oldest line is dropped
+    /// and the numbers of the others shift.
+    fn push_scrollback(&mut self, row: Row) {
+        self.scrollback.push(row);
+        if self.display_offset > 0 {
+            self.set_display_offset(self.display_offset + 1);
+        }
+    }
+
+    /// Moves the lines in `rows` up by `count` between the `cols` margins,
+    /// filling the bottom with blank lines. Lines scrolled off the top are
+    /// kept in the scrollback, but only when the region is the full screen.
+    pub fn scroll_up(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        count: usize,
+        template: &Cell,
+    ) {
+        let count = count.min(rows.len());
+        if count == 0 {
+            return;
+        }
+        if !self.is_full_width(&cols) {
+            for y in rows.start..rows.end - count {
+                self.copy_cells(y + count, y, cols.clone());
+            }
+            for y in rows.end - count..rows.end {
+                self.rows[y].erase(cols.clone(), template);
+            }
+            return;
+        }
+
+        let full_screen = rows == (0..self.rows.len());
+        let width = self.size.cols as usize;
+        for y in rows.start..rows.start + count {
+            let row = std::mem::replace(&mut self.rows[y], Row::new(width, template));
+            if full_screen {
+                self.push_scrollback(row);
+            }
+        }
+        self.rows[rows].rotate_left(count);
+    }
+
+    /// Moves the lines in `rows` down by `count` between the `cols` margins,
+    /// filling the top with blank lines
+    pub fn scroll_down(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        count: usize,
+        template: &Cell,
+    ) {
+        let count = count.min(rows.len());
+        if count == 0 {
+            return;
+        }
+        if !self.is_full_width(&cols) {
+            for y in (rows.start + count..rows.end).rev() {
+                self.copy_cells(y - count, y, cols.clone());
+            }
+            for y in rows.start..rows.start + count {
+                self.rows[y].erase(cols.clone(), template);
+            }
+            return;
+        }
+
+        self.rows[rows.clone()].rotate_right(count);
+        for row in &mut self.rows[rows.start..rows.start + count] {
+            row.reset(template);
+        }
+    }
+
+    fn is_full_width(&self, cols: &Range<usize>) -> bool {
+        *cols == (0..self.size.cols as usize)
+    }
+
+    /// Copies the cells in `cols` from line `from` to line `to`
+    fn copy_cells(&mut self, from: usize, to: usize, cols: Range<usize>) {
+        let source = &mut self.rows[from];
+        source.clear_wide(cols.start);
+        source.clear_wide(cols.end - 1);
+        let cells = source.cells[cols.clone()].to_vec();
+        let target = &mut self.rows[to];
+        target.clear_wide(cols.start);
+        target.clear_wide(cols.end - 1);
+        target.cells[cols].clone_from_slice(&cells);
+    }
+
+    /// Resizes the screen around the line of the cursor, returning by how
+    /// many rows the lines moved down, or up when negative. Lines pushed off
+    /// the top go to the scrollback, and are pulled back when the screen
+    /// grows again.
+    pub fn resize(&mut self, size: Size, cursor_y: usize) -> isize {
+        let mut shift = 0;
+        let cols = size.cols as usize;
+        let rows = size.rows as usize;
+
+        for row in &mut self.rows {
+            row.resize(cols);
+        }
+
+        if rows < self.rows.len() {
+            // Drop blank lines below the cursor first, then scroll the rest
+            // into the scrollback
+            let mut excess = self.rows.len() - rows;
+            while excess > 0
+                && self.rows.len() > cursor_y + 1
+                && self.rows.last().is_some_and(|row| row.text().is_empty())
+            {
+                self.rows.pop();
+                excess -= 1;
+            }
+            let scrolled: Vec<Row> = self.rows.drain(..excess).collect();
+            for row in scrolled {
+                self.push_scrollback(row);
+            }
+            shift = -(excess as isize);
+        } else {
+            while self.rows.len() < rows {
+                match self.scrollback.pop() {
+                    Some(mut row) => {
+                        row.resize(cols);
+                        self.rows.insert(0, row);
+                        self.display_offset = self.display_offset.saturating_sub(1);
+                        shift += 1;
+                    }
+                    None => self.rows.push(Row::new(cols, &Cell::default())),
+                }
+            }
+        }
+
+        self.size = size;
+        self.set_display_offset(self.display_offset);
+        shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A grid showing `lines`, one character per cell
+    fn grid(lines: &[&str], cols: u16, max_scrollback: usize) -> Grid {
+        let size = Size {
+            cols,
+            rows: lines.len() as u16,
+        };
+        let mut grid = Grid::new(size, max_scrollback);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                grid.row_mut(y)[x].c = c;
+            }
+        }
+        grid
+    }
+
+    fn lines(grid: &Grid) -> Vec<String> {
+        (0..grid.total_lines())
+            .map(|line| grid.line(line).text())
+            .collect()
+    }
+
+    fn view(grid: &Grid) -> Vec<String> {
+        (0..grid.rows.len())
+            .map(|y| grid.visible_row(y).unwrap().text())
+            .collect()
+    }
+
+    #[test]
+    fn scroll_into_scrollback() {
+        let mut grid = grid(&["a", "b", "c"], 4, 2);
+        let template = Cell::default();
+        grid.scroll_up(0..3, 0..4, 1, &template);
+        assert_eq!(lines(&grid), ["a", "b", "c", ""]);
+        // The oldest line is dropped once the scrollback is full
+        grid.scroll_up(0..3, 0..4, 2, &template);
+        assert_eq!(lines(&grid), ["b", "c", "", "", ""]);
+        // Lines scrolled out of a region are lost
+        grid.scroll_up(1..3, 0..4, 1, &template);
+        assert_eq!(grid.total_lines(), 5);
+    }
+
+    #[test]
+    fn scroll_within_margins() {
+        let mut grid = grid(&["abcd", "efgh", "ijkl"], 4, 10);
+        let template = Cell::default();
+        grid.scroll_up(0..3, 1..3, 1, &template);
+        assert_eq!(lines(&grid), ["afgd", "ejkh", "i  l"]);
+        grid.scroll_down(0..3, 1..3, 2, &template);
+        assert_eq!(lines(&grid), ["a  d", "e  h", "ifgl"]);
+    }
+
+    #[test]
+    fn view_stays_on_its_lines() {
+        let mut grid = grid(&["a", "b"], 4, 3);
+        let template = Cell::default();
+        grid.scroll_up(0..2, 0..4, 2, &template);
+        grid.set_display_offset(1);
+        assert_eq!(view(&grid), ["b", ""]);
+        grid.scroll_up(0..2, 0..4, 1, &template);
+        assert_eq!(grid.display_offset(), 2);
+        assert_eq!(view(&grid), ["b", ""]);
+        // Dropping the oldest line keeps the view where it is
+        grid.scroll_up(0..2, 0..4, 1, &template);
+        assert_eq!(grid.display_offset(), 3);
+        assert_eq!(view(&grid), ["b", ""]);
+        // Until the lines shown are dropped too
+        grid.scroll_up(0..2, 0..4, 1, &template);
+        assert_eq!(grid.display_offset(), 3);
+        assert_eq!(view(&grid), ["", ""]);
+    }
+
+    #[test]
+    fn resize_around_cursor() {
+        let mut grid = grid(&["a", "b", "c", "", ""], 4, 10);
+        // Blank lines below the cursor go first, then lines above it
+        let shift = grid.resize(Size { cols: 4, rows: 2 }, 2);
+        assert_eq!(shift, -1);
+        assert_eq!(lines(&grid), ["a", "b", "c"]);
+        assert_eq!(view(&grid), ["b", "c"]);
+        // Growing takes the lines back
+        let shift = grid.resize(Size { cols: 4, rows: 4 }, 1);
+        assert_eq!(shift, 1);
+        assert_eq!(view(&grid), ["a", "b", "c", ""]);
+    }
+
+    #[test]
+    fn resize_keeps_the_view() {
+        let mut grid = grid(&["a", "b", "c", "d"], 4, 10);
+        grid.resize(Size { cols: 4, rows: 2 }, 3);
+        grid.set_display_offset(1);
+        assert_eq!(view(&grid), ["b", "c"]);
+        grid.resize(Size { cols: 4, rows: 1 }, 1);
+        assert_eq!(view(&grid), ["b"]);
+        grid.resize(Size { cols: 4, rows: 3 }, 0);
+        assert_eq!(grid.display_offset(), 0);
+        assert_eq!(view(&grid), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn resize_cuts_wide_characters() {
+        let mut grid = grid(&["ab"], 4, 0);
+        grid.row_mut(0)[2] = Cell {
+            c: '字',
+            attrs: Attributes::default(),
+            width: 2,
+        };
+        grid.row_mut(0)[3].width = 0;
+        grid.resize(Size { cols: 3, rows: 1 }, 0);
+        assert_eq!(lines(&grid), ["ab"]);
+        assert_eq!(grid.line(0).cells().len(), 3);
+    }
+}
@@ -0,0 +1,1228 @@
+use crate::config::EraseKey;
+use bitflags::bitflags;
+use winit::keyboard::Key;
+use winit::keyboard::KeyCode;
+use winit::keyboard::KeyLocation;
+use winit::keyboard::ModifiersState;
+use winit::keyboard::NamedKey;
+
+bitflags! {
+    /// Progressive enhancements of the kitty keyboard protocol
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct KeyboardFlags: u8 {
+        const DISAMBIGUATE = 1 << 0;
+        const REPORT_EVENT_TYPES = 1 << 1;
+        const REPORT_ALTERNATE_KEYS = 1 << 2;
+        const REPORT_ALL_KEYS = 1 << 3;
+        const REPORT_TEXT = 1 << 4;
+    }
+}
+
+/// Keyboard flags of a screen, with the ones pushed over by `CSI > u`
+#[derive(Clone, Debug, Default)]
+pub struct KeyboardFlagsStack {
+    current: KeyboardFlags,
+    stack: Vec<KeyboardFlags>,
+}
+
+impl KeyboardFlagsStack {
+    /// The oldest flags are dropped past this depth
+    const MAX_DEPTH: usize = 16;
+
+    pub fn current(&self) -> KeyboardFlags {
+        self.current
+    }
+
+    pub fn push(&mut self, flags: KeyboardFlags) {
+        if self.stack.len() == Self::MAX_DEPTH {
+            self.stack.remove(0);
+        }
+        self.stack.push(self.current);
+        self.current = flags;
+    }
+
+    /// Pops `count` entries, popping them all resets the flags
+    pub fn pop(&mut self, count: usize) {
+        for _ in 0..count {
+            self.current = self.stack.pop().unwrap_or_default();
+        }
+    }
+
+    /// Replaces the flags (mode 1), adds to them (2) or removes from them (3)
+    pub fn set(&mut self, flags: KeyboardFlags, mode: u16) {
+        match mode {
+            1 => self.current = flags,
+            2 => self.current |= flags,
+            3 => self.current &= !flags,
+            _ => tracing::debug!("Unknown keyboard flags mode: {}", mode),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+}
+
+/// A key event, as given by winit
+#[derive(Clone, Copy, Debug)]
+pub struct KeyInput<'a> {
+    /// Key without the modifiers, `a` for Shift+A
+    pub key: &'a Key,
+    /// Key with Shift applied, `A` for Shift+A
+    pub shifted_key: &'a Key,
+    /// Key at the same place on a US keyboard
+    pub base_layout_key: Option<char>,
+    pub location: KeyLocation,
+    pub action: KeyAction,
+    /// Text typed with the modifiers, if any
+    pub text: Option<&'a str>,
+    pub modifiers: ModifiersState,
+}
+
+/// Terminal modes and settings that change what keys send
+#[derive(Clone, Copy, Debug)]
+pub struct KeyboardModes {
+    /// DECCKM, cursor keys send SS3 sequences
+    pub cursor_keys: bool,
+    /// DECKPAM, the keypad sends SS3 sequences
+    pub keypad_application: bool,
+    /// LNM, Enter sends CR LF
+    pub line_feed_new_line: bool,
+    pub backspace: EraseKey,
+    pub delete: EraseKey,
+    /// Flags of the kitty keyboard protocol, which is used when any is set
+    pub keyboard_flags: KeyboardFlags,
+    /// xterm's modifyOtherKeys level, used without the kitty protocol
+    pub modify_other_keys: u8,
+}
+
+/// Encodes a key event for the program, with the kitty keyboard protocol
+/// when enabled, or like xterm
+pub fn encode(input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    if !modes.keyboard_flags.is_empty() {
+        return encode_kitty(input, modes);
+    }
+    match input.action {
+        KeyAction::Press | KeyAction::Repeat => encode_legacy(input, modes),
+        KeyAction::Release => None,
+    }
+}
+
+/// Encodes a key press like xterm does. Keys with a sequence take the
+/// modifiers as a parameter, `CSI 1 ; 5 C` for Ctrl+Right, and other keys
+/// are prefixed with ESC when Alt is held.
+fn encode_legacy(input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    if let Some(code) = modified_key_code(input, modes.modify_other_keys) {
+        let param = modifier_param(input.modifiers);
+        return Some(format!("\x1b[27;{};{}~", param, code).into_bytes());
+    }
+    match input.key {
+        Key::Named(key) => encode_named(*key, input, modes),
+        Key::Character(c) => encode_character(c, input, modes),
+        // Dead keys and the like only send the text they may have
+        _ => input
+            .text
+            .map(|text| with_alt(text.as_bytes().to_vec(), input.modifiers)),
+    }
+}
+
+fn encode_named(key: NamedKey, input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    let modifiers = input.modifiers;
+    let cursor = |final_byte: char| cursor_sequence(final_byte, modifiers, modes.cursor_keys);
+    let tilde = |number: u8| tilde_sequence(number, modifiers);
+    // Keys sending a single control character
+    let control = |bytes: &[u8]| with_alt(bytes.to_vec(), modifiers);
+
+    let bytes = match key {
+        NamedKey::ArrowUp => cursor('A'),
+        NamedKey::ArrowDown => cursor('B'),
+        NamedKey::ArrowRight => cursor('C'),
+        NamedKey::ArrowLeft => cursor('D'),
+        NamedKey::Home => cursor('H'),
+        NamedKey::End => cursor('F'),
+        NamedKey::Insert => tilde(2),
+        NamedKey::PageUp => tilde(5),
+        NamedKey::PageDown => tilde(6),
+        NamedKey::Delete => erase(modes.delete, modifiers),
+        // Ctrl+Backspace sends the other of DEL and BS
+        NamedKey::Backspace => match modes.backspace {
+            EraseKey::Del if modifiers.control_key() => control(b"\x08"),
+            EraseKey::CtrlH if modifiers.control_key() => control(b"\x7f"),
+            erase_key => erase(erase_key, modifiers),
+        },
+        NamedKey::Enter if modes.keypad_application && input.location == KeyLocation::Numpad => {
+            b"\x1bOM".to_vec()
+        }
+        NamedKey::Enter if modes.line_feed_new_line => control(b"\r\n"),
+        NamedKey::Enter => control(b"\r"),
+        NamedKey::Tab if modifiers.shift_key() => b"\x1b[Z".to_vec(),
+        NamedKey::Tab => control(b"\t"),
+        NamedKey::Escape => control(b"\x1b"),
+        NamedKey::Space if modifiers.control_key() => control(b"\0"),
+        NamedKey::Space => control(b" "),
+        key => function_key(key, modifiers)?,
+    };
+    Some(bytes)
+}
+
+/// F1 to F4 send `SS3 P` to `SS3 S`, and the others `CSI number ~`. F13 to
+/// F24 are F1 to F12 with Shift, as in xterm.
+fn function_key(key: NamedKey, modifiers: ModifiersState) -> Option<Vec<u8>> {
+    // Numbers of F5 to F12
+    const NUMBERS: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
+    let keys = [
+        NamedKey::F1,
+        NamedKey::F2,
+        NamedKey::F3,
+        NamedKey::F4,
+        NamedKey::F5,
+        NamedKey::F6,
+        NamedKey::F7,
+        NamedKey::F8,
+        NamedKey::F9,
+        NamedKey::F10,
+        NamedKey::F11,
+        NamedKey::F12,
+        NamedKey::F13,
+        NamedKey::F14,
+        NamedKey::F15,
+        NamedKey::F16,
+        NamedKey::F17,
+        NamedKey::F18,
+        NamedKey::F19,
+        NamedKey::F20,
+        NamedKey::F21,
+        NamedKey::F22,
+        NamedKey::F23,
+        NamedKey::F24,
+    ];
+    let index = keys.iter().position(|&k| k == key)?;
+    let modifiers = if index >= 12 {
+        modifiers | ModifiersState::SHIFT
+    } else {
+        modifiers
+    };
+    let index = index % 12;
+    Some(match index {
+        0..4 => {
+            let final_byte = (b'P' + index as u8) as char;
+            match modifier_param(modifiers) {
+                1 => format!("\x1bO{}", final_byte).into_bytes(),
+                param => format!("\x1b[1;{}{}", param, final_byte).into_bytes(),
+            }
+        }
+        _ => tilde_sequence(NUMBERS[index - 4], modifiers),
+    })
+}
+
+fn encode_character(c: &str, input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    if modes.keypad_application
+        && input.location == KeyLocation::Numpad
+        && let Some(final_byte) = keypad_final_byte(c)
+    {
+        return Some(format!("\x1bO{}", final_byte).into_bytes());
+    }
+    // Ctrl+letter sends the control character whatever the platform puts
+    // in the text
+    let bytes = match control_character(c) {
+        Some(control) if input.modifiers.control_key() => vec![control],
+        _ => input.text?.as_bytes().to_vec(),
+    };
+    Some(with_alt(bytes, input.modifiers))
+}
+
+/// Code of a key sent as `CSI 27 ; modifiers ; code ~` by modifyOtherKeys.
+/// Level 1 only does so for chords that would be sent like other keys, such
+/// as Ctrl+Enter or Ctrl+Shift+A, and level 2 for any modified key but text
+/// keys with Shift alone.
+fn modified_key_code(input: &KeyInput, level: u8) -> Option<u32> {
+    let modifiers = input.modifiers;
+    let ctrl = modifiers.control_key();
+    let shift = modifiers.shift_key();
+    if level == 0 || modifier_param(modifiers) == 1 {
+        return None;
+    }
+    let (code, ambiguous) = match input.key {
+        Key::Named(NamedKey::Enter) => (13, ctrl || shift),
+        // Shift+Tab has its own sequence
+        Key::Named(NamedKey::Tab) if modifiers == ModifiersState::SHIFT => return None,
+        Key::Named(NamedKey::Tab) => (9, ctrl),
+        Key::Named(NamedKey::Backspace) => (127, false),
+        Key::Named(NamedKey::Escape) => (27, false),
+        Key::Named(NamedKey::Space) => (32, false),
+        Key::Character(c) => {
+            let shifted = single_char(input.shifted_key).or_else(|| single_char(input.key))?;
+            // Ctrl+Shift+letter sends the same as Ctrl+letter, and Ctrl
+            // doesn't change keys without a control character
+            (
+                shifted as u32,
+                ctrl && (shift || control_character(c).is_none()),
+            )
+        }
+        _ => return None,
+    };
+    let modified = match level {
+        1 => ambiguous,
+        _ => !(modifiers == ModifiersState::SHIFT && matches!(input.key, Key::Character(_))),
+    };
+    modified.then_some(code)
+}
+
+/// Final byte of the SS3 sequence sent by a keypad key in application mode
+fn keypad_final_byte(c: &str) -> Option<char> {
+    Some(match c {
+        "0" => 'p',
+        "1" => 'q',
+        "2" => 'r',
+        "3" => 's',
+        "4" => 't',
+        "5" => 'u',
+        "6" => 'v',
+        "7" => 'w',
+        "8" => 'x',
+        "9" => 'y',
+        "*" => 'j',
+        "+" => 'k',
+        "," => 'l',
+        "-" => 'm',
+        "." => 'n',
+        "/" => 'o',
+        "=" => 'X',
+        _ => return None,
+    })
+}
+
+/// Control character sent with Ctrl, as in xterm where Ctrl+2 to Ctrl+8
+/// stand for the symbols that are hard to type
+fn control_character(c: &str) -> Option<u8> {
+    let mut chars = c.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    Some(match c {
+        'a'..='z' | 'A'..='Z' => c.to_ascii_uppercase() as u8 & 0x1f,
+        '@' | '2' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' => 0x1e,
+        '_' | '/' | '7' => 0x1f,
+        '?' | '8' => 0x7f,
+        _ => return None,
+    })
+}
+
+fn erase(key: EraseKey, modifiers: ModifiersState) -> Vec<u8> {
+    match key {
+        EraseKey::Del => with_alt(vec![0x7f], modifiers),
+        EraseKey::CtrlH => with_alt(vec![0x08], modifiers),
+        EraseKey::DeleteSequence => tilde_sequence(3, modifiers),
+    }
+}
+
+/// Alt works as Meta, sending ESC first
+fn with_alt(mut bytes: Vec<u8>, modifiers: ModifiersState) -> Vec<u8> {
+    if modifiers.alt_key() {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// `CSI final` for the cursor keys, or `SS3 final` in application mode,
+/// and `CSI 1 ; modifiers final` with modifiers
+fn cursor_sequence(final_byte: char, modifiers: ModifiersState, application: bool) -> Vec<u8> {
+    match modifier_param(modifiers) {
+        1 if application => format!("\x1bO{}", final_byte),
+        1 => format!("\x1b[{}", final_byte),
+        param => format!("\x1b[1;{}{}", param, final_byte),
+    }
+    .into_bytes()
+}
+
+/// `CSI number ~`, or `CSI number ; modifiers ~` with modifiers
+fn tilde_sequence(number: u8, modifiers: ModifiersState) -> Vec<u8> {
+    match modifier_param(modifiers) {
+        1 => format!("\x1b[{}~", number),
+        param => format!("\x1b[{};{}~", number, param),
+    }
+    .into_bytes()
+}
+
+/// 1 plus 1 for Shift, 2 for Alt and 4 for Ctrl
+fn modifier_param(modifiers: ModifiersState) -> u8 {
+    1 + modifiers.shift_key() as u8
+        + 2 * modifiers.alt_key() as u8
+        + 4 * modifiers.control_key() as u8
+}
+
+/// How a key is identified in the kitty keyboard protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KittyKey {
+    /// A key typing text, by its code point without Shift
+    Text(u32),
+    /// A functional key sent as `CSI number u`
+    Code(u32),
+    /// A functional key sent as `CSI number ~`
+    Tilde(u32),
+    /// A functional key sent as `CSI 1 final`
+    Letter(char),
+}
+
+impl KittyKey {
+    const ENTER: KittyKey = KittyKey::Code(13);
+    const TAB: KittyKey = KittyKey::Code(9);
+    const BACKSPACE: KittyKey = KittyKey::Code(127);
+
+    /// Keys such as Shift or Caps Lock, only reported with all keys
+    fn is_modifier_or_lock(self) -> bool {
+        matches!(self, KittyKey::Code(57358..=57363 | 57441..=57454))
+    }
+}
+
+/// Encodes a key event with the kitty keyboard protocol:
+/// `CSI code:shifted:base ; modifiers:event ; text u`, where the trailing
+/// fields are left out when they have no value. Without
+/// [`KeyboardFlags::REPORT_ALL_KEYS`], keys typing text without Ctrl or Alt,
+/// and Enter, Tab and Backspace without modifiers, send their legacy bytes
+/// and have no release. So do keys the protocol has no code for, such as
+/// dead keys or text typed through an input method.
+fn encode_kitty(input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    let flags = modes.keyboard_flags;
+    let report_all = flags.contains(KeyboardFlags::REPORT_ALL_KEYS);
+    let event = match input.action {
+        _ if !flags.contains(KeyboardFlags::REPORT_EVENT_TYPES) => 1,
+        KeyAction::Press => 1,
+        KeyAction::Repeat => 2,
+        KeyAction::Release => 3,
+    };
+    if input.action == KeyAction::Release && event == 1 {
+        return None;
+    }
+    let modifiers = kitty_modifiers(input.modifiers);
+    let Some(key) = kitty_key(input) else {
+        return encode_kitty_legacy(input, modes);
+    };
+
+    if !report_all {
+        let legacy = match key {
+            // Keypad keys typing text are text keys here
+            _ if matches!(input.key, Key::Character(_)) => modifiers & !1 == 0,
+            KittyKey::ENTER | KittyKey::TAB | KittyKey::BACKSPACE => modifiers == 0,
+            KittyKey::Letter(_) | KittyKey::Tilde(_) => modifiers == 0 && event == 1,
+            key => {
+                if key.is_modifier_or_lock() {
+                    return None;
+                }
+                false
+            }
+        };
+        if legacy {
+            return encode_kitty_legacy(input, modes);
+        }
+    }
+
+    let mut code = match key {
+        KittyKey::Text(code) | KittyKey::Code(code) | KittyKey::Tilde(code) => code.to_string(),
+        KittyKey::Letter(_) => "1".to_string(),
+    };
+    if flags.contains(KeyboardFlags::REPORT_ALTERNATE_KEYS)
+        && let KittyKey::Text(unshifted) = key
+    {
+        let shifted = single_char(input.shifted_key)
+            .map(|c| c as u32)
+            .filter(|&shifted| input.modifiers.shift_key() && shifted != unshifted);
+        let base = input
+            .base_layout_key
+            .map(|c| c as u32)
+            .filter(|&base| base != unshifted);
+        match (shifted, base) {
+            (Some(shifted), Some(base)) => code += &format!(":{}:{}", shifted, base),
+            (Some(shifted), None) => code += &format!(":{}", shifted),
+            (None, Some(base)) => code += &format!("::{}", base),
+            (None, None) => {}
+        }
+    }
+
+    // The text of releases isn't sent, nor control characters
+    let text: Vec<String> = match input.text {
+        Some(text)
+            if report_all
+                && flags.contains(KeyboardFlags::REPORT_TEXT)
+                && input.action != KeyAction::Release =>
+        {
+            text.chars()
+                .filter(|c| !c.is_control())
+                .map(|c| (c as u32).to_string())
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let mut params = vec![code];
+    if modifiers != 0 || event != 1 || !text.is_empty() {
+        params.push(match event {
+            1 => (modifiers + 1).to_string(),
+            event => format!("{}:{}", modifiers + 1, event),
+        });
+    }
+    if !text.is_empty() {
+        params.push(text.join(":"));
+    }
+    let sequence = match key {
+        KittyKey::Text(_) | KittyKey::Code(_) => format!("\x1b[{}u", params.join(";")),
+        KittyKey::Tilde(_) => format!("\x1b[{}~", params.join(";")),
+        // The 1 is implied when there are no other parameters
+        KittyKey::Letter(final_byte) if params.len() == 1 => format!("\x1b[{}", final_byte),
+        KittyKey::Letter(final_byte) => format!("\x1b[{}{}", params.join(";"), final_byte),
+    };
+    Some(sequence.into_bytes())
+}
+
+/// Legacy bytes of a key press left out of the kitty keyboard protocol
+fn encode_kitty_legacy(input: &KeyInput, modes: &KeyboardModes) -> Option<Vec<u8>> {
+    // modifyOtherKeys doesn't apply along with the kitty protocol
+    let modes = KeyboardModes {
+        modify_other_keys: 0,
+        ..*modes
+    };
+    match input.action {
+        KeyAction::Release => None,
+        _ => encode_legacy(input, &modes),
+    }
+}
+
+/// Shift 1, Alt 2, Ctrl 4 and Super 8
+fn kitty_modifiers(modifiers: ModifiersState) -> u8 {
+    modifiers.shift_key() as u8
+        + 2 * modifiers.alt_key() as u8
+        + 4 * modifiers.control_key() as u8
+        + 8 * modifiers.super_key() as u8
+}
+
+fn single_char(key: &Key) -> Option<char> {
+    let Key::Character(c) = key else {
+        return None;
+    };
+    let mut chars = c.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+fn kitty_key(input: &KeyInput) -> Option<KittyKey> {
+    let numpad = input.location == KeyLocation::Numpad;
+    let right = input.location == KeyLocation::Right;
+    if let Some(c) = single_char(input.key) {
+        if numpad {
+            let code = match c {
+                '0'..='9' => 57399 + c as u32 - '0' as u32,
+                '.' => 57409,
+                '/' => 57410,
+                '*' => 57411,
+                '-' => 57412,
+                '+' => 57413,
+                '=' => 57415,
+                ',' => 57416,
+                _ => return Some(KittyKey::Text(c as u32)),
+            };
+            return Some(KittyKey::Code(code));
+        }
+        return Some(KittyKey::Text(c.to_lowercase().next().unwrap_or(c) as u32));
+    }
+
+    let Key::Named(key) = input.key else {
+        return None;
+    };
+    let function_keys = [
+        NamedKey::F13,
+        NamedKey::F14,
+        NamedKey::F15,
+        NamedKey::F16,
+        NamedKey::F17,
+        NamedKey::F18,
+        NamedKey::F19,
+        NamedKey::F20,
+        NamedKey::F21,
+        NamedKey::F22,
+        NamedKey::F23,
+        NamedKey::F24,
+        NamedKey::F25,
+        NamedKey::F26,
+        NamedKey::F27,
+        NamedKey::F28,
+        NamedKey::F29,
+        NamedKey::F30,
+        NamedKey::F31,
+        NamedKey::F32,
+        NamedKey::F33,
+        NamedKey::F34,
+        NamedKey::F35,
+    ];
+    if let Some(index) = function_keys.iter().position(|k| k == key) {
+        return Some(KittyKey::Code(57376 + index as u32));
+    }
+    // Keypad keys typed without Num Lock
+    if numpad {
+        let code = match key {
+            NamedKey::Enter => 57414,
+            NamedKey::ArrowLeft => 57417,
+            NamedKey::ArrowRight => 57418,
+            NamedKey::ArrowUp => 57419,
+            NamedKey::ArrowDown => 57420,
+            NamedKey::PageUp => 57421,
+            NamedKey::PageDown => 57422,
+            NamedKey::Home => 57423,
+            NamedKey::End => 57424,
+            NamedKey::Insert => 57425,
+            NamedKey::Delete => 57426,
+            NamedKey::Clear => 57427,
+            _ => 0,
+        };
+        if code != 0 {
+            return Some(KittyKey::Code(code));
+        }
+    }
+    // Left and right modifiers have their own codes
+    let side = |left: u32| KittyKey::Code(if right { left + 6 } else { left });
+    Some(match key {
+        NamedKey::Escape => KittyKey::Code(27),
+        NamedKey::Enter => KittyKey::ENTER,
+        NamedKey::Tab => KittyKey::TAB,
+        NamedKey::Backspace => KittyKey::BACKSPACE,
+        NamedKey::Space => KittyKey::Text(' ' as u32),
+        NamedKey::Insert => KittyKey::Tilde(2),
+        NamedKey::Delete => KittyKey::Tilde(3),
+        NamedKey::PageUp => KittyKey::Tilde(5),
+        NamedKey::PageDown => KittyKey::Tilde(6),
+        NamedKey::ArrowUp => KittyKey::Letter('A'),
+        NamedKey::ArrowDown => KittyKey::Letter('B'),
+        NamedKey::ArrowRight => KittyKey::Letter('C'),
+        NamedKey::ArrowLeft => KittyKey::Letter('D'),
+        NamedKey::Home => KittyKey::Letter('H'),
+        NamedKey::End => KittyKey::Letter('F'),
+        NamedKey::F1 => KittyKey::Letter('P'),
+        NamedKey::F2 => KittyKey::Letter('Q'),
+        // CSI R would be taken for a cursor position report
+        NamedKey::F3 => KittyKey::Tilde(13),
+        NamedKey::F4 => KittyKey::Letter('S'),
+        NamedKey::F5 => KittyKey::Tilde(15),
+        NamedKey::F6 => KittyKey::Tilde(17),
+        NamedKey::F7 => KittyKey::Tilde(18),
+        NamedKey::F8 => KittyKey::Tilde(19),
+        NamedKey::F9 => KittyKey::Tilde(20),
+        NamedKey::F10 => KittyKey::Tilde(21),
+        NamedKey::F11 => KittyKey::Tilde(23),
+        NamedKey::F12 => KittyKey::Tilde(24),
+        NamedKey::CapsLock => KittyKey::Code(57358),
+        NamedKey::ScrollLock => KittyKey::Code(57359),
+        NamedKey::NumLock => KittyKey::Code(57360),
+        NamedKey::PrintScreen => KittyKey::Code(57361),
+        NamedKey::Pause => KittyKey::Code(57362),
+        NamedKey::ContextMenu => KittyKey::Code(57363),
+        NamedKey::Shift => side(57441),
+        NamedKey::Control => side(57442),
+        NamedKey::Alt => side(57443),
+        NamedKey::Super => side(57444),
+        NamedKey::Hyper => side(57445),
+        NamedKey::Meta => side(57446),
+        NamedKey::AltGraph => KittyKey::Code(57453),
+        _ => return None,
+    })
+}
+
+/// Character typed by a physical key on a US keyboard, reported as the base
+/// layout key by the kitty keyboard protocol
+pub fn base_layout_key(code: KeyCode) -> Option<char> {
+    let letters = [
+        KeyCode::KeyA,
+        KeyCode::KeyB,
+        KeyCode::KeyC,
+        KeyCode::KeyD,
+        KeyCode::KeyE,
+        KeyCode::KeyF,
+        KeyCode::KeyG,
+        KeyCode::KeyH,
+        KeyCode::KeyI,
+        KeyCode::KeyJ,
+        KeyCode::KeyK,
+        KeyCode::KeyL,
+        KeyCode::KeyM,
+        KeyCode::KeyN,
+        KeyCode::KeyO,
+        KeyCode::KeyP,
+        KeyCode::KeyQ,
+        KeyCode::KeyR,
+        KeyCode::KeyS,
+        KeyCode::KeyT,
+        KeyCode::KeyU,
+        KeyCode::KeyV,
+        KeyCode::KeyW,
+        KeyCode::KeyX,
+        KeyCode::KeyY,
+        KeyCode::KeyZ,
+    ];
+    let digits = [
+        KeyCode::Digit0,
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    if let Some(index) = letters.iter().position(|&key| key == code) {
+        return Some((b'a' + index as u8) as char);
+    }
+    if let Some(index) = digits.iter().position(|&key| key == code) {
+        return Some((b'0' + index as u8) as char);
+    }
+    Some(match code {
+        KeyCode::Backquote => '`',
+        KeyCode::Minus => '-',
+        KeyCode::Equal => '=',
+        KeyCode::BracketLeft => '[',
+        KeyCode::BracketRight => ']',
+        KeyCode::Backslash => '\\',
+        KeyCode::Semicolon => ';',
+        KeyCode::Quote => '\'',
+        KeyCode::Comma => ',',
+        KeyCode::Period => '.',
+        KeyCode::Slash => '/',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key event owning what [`KeyInput`] borrows
+    struct Event {
+        key: Key,
+        shifted_key: Key,
+        base_layout_key: Option<char>,
+        location: KeyLocation,
+        action: KeyAction,
+        text: Option<String>,
+        modifiers: ModifiersState,
+    }
+
+    impl Event {
+        fn named(key: NamedKey) -> Event {
+            Event {
+                key: Key::Named(key),
+                shifted_key: Key::Named(key),
+                base_layout_key: None,
+                location: KeyLocation::Standard,
+                action: KeyAction::Press,
+                text: None,
+                modifiers: ModifiersState::empty(),
+            }
+        }
+
+        fn character(c: &str) -> Event {
+            Event {
+                key: Key::Character(c.into()),
+                shifted_key: Key::Character(c.into()),
+                base_layout_key: c.chars().next().filter(char::is_ascii),
+                text: Some(c.to_string()),
+                ..Event::named(NamedKey::Space)
+            }
+        }
+
+        fn with(self, modifiers: ModifiersState) -> Event {
+            Event { modifiers, ..self }
+        }
+
+        fn numpad(self) -> Event {
+            Event {
+                location: KeyLocation::Numpad,
+                ..self
+            }
+        }
+
+        fn encode(&self, modes: &KeyboardModes) -> Option<String> {
+            let input = KeyInput {
+                key: &self.key,
+                shifted_key: &self.shifted_key,
+                base_layout_key: self.base_layout_key,
+                location: self.location,
+                action: self.action,
+                text: self.text.as_deref(),
+                modifiers: self.modifiers,
+            };
+            encode(&input, modes).map(|bytes| String::from_utf8(bytes).unwrap())
+        }
+    }
+
+    fn modes() -> KeyboardModes {
+        KeyboardModes {
+            cursor_keys: false,
+            keypad_application: false,
+            line_feed_new_line: false,
+            backspace: EraseKey::Del,
+            delete: EraseKey::DeleteSequence,
+            keyboard_flags: KeyboardFlags::empty(),
+            modify_other_keys: 0,
+        }
+    }
+
+    const SHIFT: ModifiersState = ModifiersState::SHIFT;
+    const CTRL: ModifiersState = ModifiersState::CONTROL;
+    const ALT: ModifiersState = ModifiersState::ALT;
+
+    fn key(key: NamedKey, modifiers: ModifiersState, modes: &KeyboardModes) -> Option<String> {
+        Event::named(key).with(modifiers).encode(modes)
+    }
+
+    #[test]
+    fn modifier_params() {
+        let modes = modes();
+        assert_eq!(key(NamedKey::ArrowUp, CTRL, &modes).unwrap(), "\x1b[1;5A");
+        assert_eq!(
+            key(NamedKey::ArrowRight, SHIFT | ALT, &modes).unwrap(),
+            "\x1b[1;4C"
+        );
+        assert_eq!(key(NamedKey::End, SHIFT, &modes).unwrap(), "\x1b[1;2F");
+        assert_eq!(key(NamedKey::PageUp, CTRL, &modes).unwrap(), "\x1b[5;5~");
+        assert_eq!(key(NamedKey::F1, SHIFT, &modes).unwrap(), "\x1b[1;2P");
+        assert_eq!(key(NamedKey::F5, CTRL, &modes).unwrap(), "\x1b[15;5~");
+        // F13 and up are F1 to F12 with Shift
+        assert_eq!(key(NamedKey::F13, CTRL, &modes).unwrap(), "\x1b[1;6P");
+        assert_eq!(
+            key(NamedKey::F24, ModifiersState::empty(), &modes).unwrap(),
+            "\x1b[24;2~"
+        );
+        assert_eq!(key(NamedKey::Tab, SHIFT, &modes).unwrap(), "\x1b[Z");
+        // Keys without a sequence
+        assert_eq!(key(NamedKey::Shift, SHIFT, &modes), None);
+        assert_eq!(key(NamedKey::F25, ModifiersState::empty(), &modes), None);
+    }
+
+    #[test]
+    fn cursor_keys() {
+        let mut modes = modes();
+        let none = ModifiersState::empty();
+        assert_eq!(key(NamedKey::ArrowUp, none, &modes).unwrap(), "\x1b[A");
+        assert_eq!(key(NamedKey::Home, none, &modes).unwrap(), "\x1b[H");
+        assert_eq!(key(NamedKey::F1, none, &modes).unwrap(), "\x1bOP");
+        modes.cursor_keys = true;
+        assert_eq!(key(NamedKey::ArrowUp, none, &modes).unwrap(), "\x1bOA");
+        assert_eq!(key(NamedKey::ArrowLeft, none, &modes).unwrap(), "\x1bOD");
+        assert_eq!(key(NamedKey::Home, none, &modes).unwrap(), "\x1bOH");
+        // Modified keys send CSI in both modes
+        assert_eq!(key(NamedKey::ArrowUp, CTRL, &modes).unwrap(), "\x1b[1;5A");
+        // Other keys don't change
+        assert_eq!(key(NamedKey::PageDown, none, &modes).unwrap(), "\x1b[6~");
+    }
+
+    #[test]
+    fn erase_keys() {
+        let mut modes = modes();
+        let none = ModifiersState::empty();
+        assert_eq!(key(NamedKey::Backspace, none, &modes).unwrap(), "\x7f");
+        assert_eq!(key(NamedKey::Delete, none, &modes).unwrap(), "\x1b[3~");
+        assert_eq!(key(NamedKey::Delete, SHIFT, &modes).unwrap(), "\x1b[3;2~");
+        // Ctrl+Backspace sends the other erase byte
+        assert_eq!(key(NamedKey::Backspace, CTRL, &modes).unwrap(), "\x08");
+
+        modes.backspace = EraseKey::CtrlH;
+        modes.delete = EraseKey::Del;
+        assert_eq!(key(NamedKey::Backspace, none, &modes).unwrap(), "\x08");
+        assert_eq!(key(NamedKey::Backspace, CTRL, &modes).unwrap(), "\x7f");
+        assert_eq!(key(NamedKey::Delete, none, &modes).unwrap(), "\x7f");
+
+        modes.backspace = EraseKey::DeleteSequence;
+        modes.delete = EraseKey::CtrlH;
+        assert_eq!(key(NamedKey::Backspace, none, &modes).unwrap(), "\x1b[3~");
+        assert_eq!(key(NamedKey::Delete, none, &modes).unwrap(), "\x08");
+    }
+
+    #[test]
+    fn alt_prefix() {
+        let modes = modes();
+        assert_eq!(
+            Event::character("a").with(ALT).encode(&modes).unwrap(),
+            "\x1ba"
+        );
+        assert_eq!(
+            Event::character("é").with(ALT).encode(&modes).unwrap(),
+            "\x1bé"
+        );
+        assert_eq!(
+            Event::character("a")
+                .with(ALT | CTRL)
+                .encode(&modes)
+                .unwrap(),
+            "\x1b\x01"
+        );
+        assert_eq!(key(NamedKey::Backspace, ALT, &modes).unwrap(), "\x1b\x7f");
+        assert_eq!(key(NamedKey::Enter, ALT, &modes).unwrap(), "\x1b\r");
+        assert_eq!(key(NamedKey::Escape, ALT, &modes).unwrap(), "\x1b\x1b");
+        // Keys with a sequence take Alt as a parameter
+        assert_eq!(key(NamedKey::ArrowUp, ALT, &modes).unwrap(), "\x1b[1;3A");
+        assert_eq!(key(NamedKey::Delete, ALT, &modes).unwrap(), "\x1b[3;3~");
+    }
+
+    #[test]
+    fn control_characters() {
+        let modes = modes();
+        assert_eq!(
+            Event::character("c").with(CTRL).encode(&modes).unwrap(),
+            "\x03"
+        );
+        assert_eq!(
+            Event::character("2").with(CTRL).encode(&modes).unwrap(),
+            "\0"
+        );
+        assert_eq!(
+            Event::character("[").with(CTRL).encode(&modes).unwrap(),
+            "\x1b"
+        );
+        assert_eq!(key(NamedKey::Space, CTRL, &modes).unwrap(), "\0");
+        // Characters without a control character send their text
+        assert_eq!(
+            Event::character("é").with(CTRL).encode(&modes).unwrap(),
+            "é"
+        );
+        let mut shifted = Event::character("a").with(SHIFT);
+        shifted.shifted_key = Key::Character("A".into());
+        shifted.text = Some("A".to_string());
+        assert_eq!(shifted.encode(&modes).unwrap(), "A");
+        // Releases send nothing
+        let mut release = Event::character("a");
+        release.action = KeyAction::Release;
+        assert_eq!(release.encode(&modes), None);
+    }
+
+    #[test]
+    fn keypad() {
+        let mut modes = modes();
+        let five = Event::character("5").numpad();
+        let enter = Event::named(NamedKey::Enter).numpad();
+        assert_eq!(five.encode(&modes).unwrap(), "5");
+        assert_eq!(enter.encode(&modes).unwrap(), "\r");
+        modes.line_feed_new_line = true;
+        assert_eq!(enter.encode(&modes).unwrap(), "\r\n");
+
+        modes.keypad_application = true;
+        assert_eq!(five.encode(&modes).unwrap(), "\x1bOu");
+        assert_eq!(
+            Event::character("+").numpad().encode(&modes).unwrap(),
+            "\x1bOk"
+        );
+        assert_eq!(enter.encode(&modes).unwrap(), "\x1bOM");
+        // Only the keypad is affected
+        assert_eq!(Event::character("5").encode(&modes).unwrap(), "5");
+        assert_eq!(
+            key(NamedKey::Enter, ModifiersState::empty(), &modes).unwrap(),
+            "\r\n"
+        );
+    }
+
+    #[test]
+    fn keys_without_kitty_code() {
+        let mut modes = modes();
+        for flags in [
+            KeyboardFlags::DISAMBIGUATE,
+            KeyboardFlags::all() - KeyboardFlags::REPORT_TEXT,
+            KeyboardFlags::all(),
+        ] {
+            modes.keyboard_flags = flags;
+            // Text from an input method
+            let mut text = Event::character("日本");
+            assert_eq!(text.encode(&modes).unwrap(), "日本");
+            text.action = KeyAction::Release;
+            assert_eq!(text.encode(&modes), None);
+            // Dead keys only send the text they compose, if any
+            let mut dead = Event::named(NamedKey::Space);
+            dead.key = Key::Dead(Some('´'));
+            assert_eq!(dead.encode(&modes), None);
+            dead.text = Some("´".to_string());
+            assert_eq!(dead.encode(&modes).unwrap(), "´");
+        }
+    }
+
+    fn kitty(flags: KeyboardFlags) -> KeyboardModes {
+        KeyboardModes {
+            keyboard_flags: flags,
+            ..modes()
+        }
+    }
+
+    /// A key typed with Shift, along with the key it's typed on in the US
+    /// layout
+    fn shifted(c: &str, shifted: &str, base: char) -> Event {
+        Event {
+            shifted_key: Key::Character(shifted.into()),
+            base_layout_key: Some(base),
+            text: Some(shifted.to_string()),
+            ..Event::character(c).with(SHIFT)
+        }
+    }
+
+    fn with_action(event: Event, action: KeyAction) -> Event {
+        Event { action, ..event }
+    }
+
+    #[test]
+    fn kitty_disambiguate() {
+        let modes = kitty(KeyboardFlags::DISAMBIGUATE);
+        let none = ModifiersState::empty();
+        assert_eq!(key(NamedKey::Escape, none, &modes).unwrap(), "\x1b[27u");
+        assert_eq!(key(NamedKey::Escape, ALT, &modes).unwrap(), "\x1b[27;3u");
+        assert_eq!(
+            Event::character("a").with(CTRL).encode(&modes).unwrap(),
+            "\x1b[97;5u"
+        );
+        assert_eq!(
+            Event::character("a").with(ALT).encode(&modes).unwrap(),
+            "\x1b[97;3u"
+        );
+        assert_eq!(key(NamedKey::Enter, CTRL, &modes).unwrap(), "\x1b[13;5u");
+        assert_eq!(key(NamedKey::F5, CTRL, &modes).unwrap(), "\x1b[15;5~");
+        assert_eq!(key(NamedKey::ArrowUp, SHIFT, &modes).unwrap(), "\x1b[1;2A");
+        assert_eq!(key(NamedKey::F3, SHIFT, &modes).unwrap(), "\x1b[13;2~");
+        assert_eq!(key(NamedKey::F13, none, &modes).unwrap(), "\x1b[57376u");
+        // Text and unmodified keys send their legacy bytes
+        assert_eq!(Event::character("a").encode(&modes).unwrap(), "a");
+        assert_eq!(shifted("a", "A", 'a').encode(&modes).unwrap(), "A");
+        assert_eq!(Event::character("5").numpad().encode(&modes).unwrap(), "5");
+        assert_eq!(key(NamedKey::Enter, none, &modes).unwrap(), "\r");
+        assert_eq!(key(NamedKey::Backspace, none, &modes).unwrap(), "\x7f");
+        assert_eq!(key(NamedKey::ArrowUp, none, &modes).unwrap(), "\x1b[A");
+        // Shift+Tab has a modifier, so it is reported
+        assert_eq!(key(NamedKey::Tab, SHIFT, &modes).unwrap(), "\x1b[9;2u");
+        // Releases aren't reported
+        let release = with_action(Event::named(NamedKey::Escape), KeyAction::Release);
+        assert_eq!(release.encode(&modes), None);
+        let repeat = with_action(Event::named(NamedKey::Escape), KeyAction::Repeat);
+        assert_eq!(repeat.encode(&modes).unwrap(), "\x1b[27u");
+    }
+
+    #[test]
+    fn kitty_event_types() {
+        let modes = kitty(KeyboardFlags::DISAMBIGUATE | KeyboardFlags::REPORT_EVENT_TYPES);
+        let ctrl_a = || Event::character("a").with(CTRL);
+        assert_eq!(ctrl_a().encode(&modes).unwrap(), "\x1b[97;5u");
+        let repeat = with_action(ctrl_a(), KeyAction::Repeat);
+        assert_eq!(repeat.encode(&modes).unwrap(), "\x1b[97;5:2u");
+        let release = with_action(ctrl_a(), KeyAction::Release);
+        assert_eq!(release.encode(&modes).unwrap(), "\x1b[97;5:3u");
+        let release = with_action(Event::named(NamedKey::Escape), KeyAction::Release);
+        assert_eq!(release.encode(&modes).unwrap(), "\x1b[27;1:3u");
+        // Functional keys report repeats, with the 1 of letter keys
+        let repeat = with_action(Event::named(NamedKey::ArrowUp), KeyAction::Repeat);
+        assert_eq!(repeat.encode(&modes).unwrap(), "\x1b[1;1:2A");
+        let release = with_action(Event::named(NamedKey::PageUp), KeyAction::Release);
+        assert_eq!(release.encode(&modes).unwrap(), "\x1b[5;1:3~");
+        // Keys sent as legacy bytes have no release
+        let release = with_action(Event::character("a"), KeyAction::Release);
+        assert_eq!(release.encode(&modes), None);
+        let release = with_action(Event::named(NamedKey::Enter), KeyAction::Release);
+        assert_eq!(release.encode(&modes), None);
+    }
+
+    #[test]
+    fn kitty_alternate_keys() {
+        let modes = kitty(KeyboardFlags::DISAMBIGUATE | KeyboardFlags::REPORT_ALTERNATE_KEYS);
+        let ctrl_shift_a = shifted("a", "A", 'a').with(CTRL | SHIFT);
+        assert_eq!(ctrl_shift_a.encode(&modes).unwrap(), "\x1b[97:65;6u");
+        // The base layout key is reported when it differs
+        let mut ctrl_ef = Event::character("ф").with(CTRL);
+        ctrl_ef.base_layout_key = Some('a');
+        assert_eq!(ctrl_ef.encode(&modes).unwrap(), "\x1b[1092::97;5u");
+        let ctrl_shift_ef = shifted("ф", "Ф", 'a').with(CTRL | SHIFT);
+        assert_eq!(
+            ctrl_shift_ef.encode(&modes).unwrap(),
+            "\x1b[1092:1060:97;6u"
+        );
+        // The shifted key is only reported with Shift
+        let mut ctrl_1 = Event::character("1").with(CTRL);
+        ctrl_1.shifted_key = Key::Character("!".into());
+        assert_eq!(ctrl_1.encode(&modes).unwrap(), "\x1b[49;5u");
+        // Functional keys have no alternate keys
+        assert_eq!(key(NamedKey::Escape, SHIFT, &modes).unwrap(), "\x1b[27;2u");
+    }
+
+    #[test]
+    fn kitty_report_all_keys() {
+        let mut modes = kitty(KeyboardFlags::REPORT_ALL_KEYS);
+        let none = ModifiersState::empty();
+        assert_eq!(Event::character("a").encode(&modes).unwrap(), "\x1b[97u");
+        assert_eq!(shifted("a", "A", 'a').encode(&modes).unwrap(), "\x1b[97;2u");
+        assert_eq!(
+            Event::character("5").numpad().encode(&modes).unwrap(),
+            "\x1b[57404u"
+        );
+        assert_eq!(key(NamedKey::Enter, none, &modes).unwrap(), "\x1b[13u");
+        assert_eq!(key(NamedKey::Backspace, none, &modes).unwrap(), "\x1b[127u");
+        assert_eq!(key(NamedKey::Space, none, &modes).unwrap(), "\x1b[32u");
+        assert_eq!(key(NamedKey::ArrowUp, none, &modes).unwrap(), "\x1b[A");
+
+        // The text is sent along with the key
+        modes.keyboard_flags |= KeyboardFlags::REPORT_TEXT | KeyboardFlags::REPORT_EVENT_TYPES;
+        assert_eq!(
+            Event::character("a").encode(&modes).unwrap(),
+            "\x1b[97;1;97u"
+        );
+        assert_eq!(
+            shifted("a", "A", 'a').encode(&modes).unwrap(),
+            "\x1b[97;2;65u"
+        );
+        let mut compose = Event::character("e");
+        compose.text = Some("ë".to_string());
+        assert_eq!(compose.encode(&modes).unwrap(), "\x1b[101;1;235u");
+        // But not control characters nor on release
+        let mut ctrl_a = Event::character("a").with(CTRL);
+        ctrl_a.text = Some("\x01".to_string());
+        assert_eq!(ctrl_a.encode(&modes).unwrap(), "\x1b[97;5u");
+        let release = with_action(Event::character("a"), KeyAction::Release);
+        assert_eq!(release.encode(&modes).unwrap(), "\x1b[97;1:3u");
+
+        // The text is only sent when all keys are reported
+        modes.keyboard_flags = KeyboardFlags::DISAMBIGUATE | KeyboardFlags::REPORT_TEXT;
+        assert_eq!(Event::character("a").encode(&modes).unwrap(), "a");
+        assert_eq!(
+            Event::character("a").with(ALT).encode(&modes).unwrap(),
+            "\x1b[97;3u"
+        );
+    }
+
+    #[test]
+    fn kitty_modifier_and_lock_keys() {
+        let mut modes = kitty(KeyboardFlags::DISAMBIGUATE);
+        assert_eq!(key(NamedKey::Shift, SHIFT, &modes), None);
+        assert_eq!(
+            key(NamedKey::CapsLock, ModifiersState::empty(), &modes),
+            None
+        );
+
+        modes.keyboard_flags = KeyboardFlags::REPORT_ALL_KEYS | KeyboardFlags::REPORT_EVENT_TYPES;
+        assert_eq!(
+            key(NamedKey::Shift, SHIFT, &modes).unwrap(),
+            "\x1b[57441;2u"
+        );
+        let mut right_ctrl = Event::named(NamedKey::Control).with(CTRL);
+        right_ctrl.location = KeyLocation::Right;
+        assert_eq!(right_ctrl.encode(&modes).unwrap(), "\x1b[57448;5u");
+        assert_eq!(
+            key(NamedKey::CapsLock, ModifiersState::empty(), &modes).unwrap(),
+            "\x1b[57358u"
+        );
+        assert_eq!(
+            key(NamedKey::AltGraph, ModifiersState::empty(), &modes).unwrap(),
+            "\x1b[57453u"
+        );
+        // The release is reported once the modifier is off
+        let release = with_action(Event::named(NamedKey::Shift), KeyAction::Release);
+        assert_eq!(release.encode(&modes).unwrap(), "\x1b[57441;1:3u");
+    }
+
+    #[test]
+    fn keyboard_flags_stack() {
+        let flags = |bits| KeyboardFlags::from_bits_truncate(bits);
+        let mut stack = KeyboardFlagsStack::default();
+        stack.push(flags(1));
+        stack.push(flags(3));
+        assert_eq!(stack.current(), flags(3));
+        stack.pop(1);
+        assert_eq!(stack.current(), flags(1));
+        // Popping more than was pushed resets the flags
+        stack.pop(5);
+        assert_eq!(stack.current(), flags(0));
+
+        stack.set(flags(5), 1);
+        assert_eq!(stack.current(), flags(5));
+        stack.set(flags(10), 2);
+        assert_eq!(stack.current(), flags(15));
+        stack.set(flags(6), 3);
+        assert_eq!(stack.current(), flags(9));
+        stack.set(flags(31), 4);
+        assert_eq!(stack.current(), flags(9));
+
+        // Past the maximum depth the oldest flags are dropped
+        let mut stack = KeyboardFlagsStack::default();
+        for bits in 1..=20 {
+            stack.push(flags(bits));
+        }
+        stack.pop(KeyboardFlagsStack::MAX_DEPTH);
+        assert_eq!(stack.current(), flags(4));
+        stack.pop(1);
+        assert_eq!(stack.current(), flags(0));
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut modes = modes();
+        let ctrl_shift_a = || shifted("a", "A", 'a').with(CTRL | SHIFT);
+        let none = ModifiersState::empty();
+
+        // Level 2 sends all modified keys but Shift+text as CSI 27
+        modes.modify_other_keys = 2;
+        assert_eq!(
+            Event::character("a").with(CTRL).encode(&modes).unwrap(),
+            "\x1b[27;5;97~"
+        );
+        assert_eq!(ctrl_shift_a().encode(&modes).unwrap(), "\x1b[27;6;65~");
+        assert_eq!(
+            Event::character("a").with(ALT).encode(&modes).unwrap(),
+            "\x1b[27;3;97~"
+        );
+        assert_eq!(key(NamedKey::Enter, CTRL, &modes).unwrap(), "\x1b[27;5;13~");
+        assert_eq!(key(NamedKey::Tab, CTRL, &modes).unwrap(), "\x1b[27;5;9~");
+        assert_eq!(
+            key(NamedKey::Backspace, ALT, &modes).unwrap(),
+            "\x1b[27;3;127~"
+        );
+        assert_eq!(
+            key(NamedKey::Escape, CTRL, &modes).unwrap(),
+            "\x1b[27;5;27~"
+        );
+        assert_eq!(key(NamedKey::Space, CTRL, &modes).unwrap(), "\x1b[27;5;32~");
+        assert_eq!(shifted("a", "A", 'a').encode(&modes).unwrap(), "A");
+        assert_eq!(key(NamedKey::Tab, SHIFT, &modes).unwrap(), "\x1b[Z");
+        // Unmodified keys and keys with their own sequence don't change
+        assert_eq!(Event::character("a").encode(&modes).unwrap(), "a");
+        assert_eq!(key(NamedKey::Enter, none, &modes).unwrap(), "\r");
+        assert_eq!(key(NamedKey::ArrowUp, CTRL, &modes).unwrap(), "\x1b[1;5A");
+
+        // Level 1 leaves out the keys with a usual encoding
+        modes.modify_other_keys = 1;
+        assert_eq!(
+            Event::character("a").with(CTRL).encode(&modes).unwrap(),
+            "\x01"
+        );
+        assert_eq!(
+            Event::character("a").with(ALT).encode(&modes).unwrap(),
+            "\x1ba"
+        );
+        assert_eq!(key(NamedKey::Enter, ALT, &modes).unwrap(), "\x1b\r");
+        assert_eq!(key(NamedKey::Backspace, CTRL, &modes).unwrap(), "\x08");
+        assert_eq!(key(NamedKey::Space, CTRL, &modes).unwrap(), "\0");
+        assert_eq!(key(NamedKey::Escape, CTRL, &modes).unwrap(), "\x1b");
+        // But not the ones sending the same as another chord
+        assert_eq!(ctrl_shift_a().encode(&modes).unwrap(), "\x1b[27;6;65~");
+        assert_eq!(key(NamedKey::Enter, CTRL, &modes).unwrap(), "\x1b[27;5;13~");
+        assert_eq!(
+            key(NamedKey::Enter, SHIFT, &modes).unwrap(),
+            "\x1b[27;2;13~"
+        );
+        assert_eq!(key(NamedKey::Tab, CTRL, &modes).unwrap(), "\x1b[27;5;9~");
+        assert_eq!(
+            Event::character("é").with(CTRL).encode(&modes).unwrap(),
+            "\x1b[27;5;233~"
+        );
+        assert_eq!(
+            Event::character("1").with(CTRL).encode(&modes).unwrap(),
+            "\x1b[27;5;49~"
+        );
+    }
+}
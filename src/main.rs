@@ -1,6 +1,12 @@
 use crate::application::Application;
+use crate::config::Config;
+use crate::notify::CommandNotifier;
+use crate::notify::SharedNotifier;
 use crate::pty::PtySession;
 use crate::terminal::Terminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
 use tracing_subscriber::filter::EnvFilter;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
@@ -9,7 +15,21 @@ use winit::event_loop::ControlFlow;
 use winit::event_loop::EventLoop;
 
 mod application;
+mod charset;
+mod clipboard;
+mod color;
+mod config;
+mod grid;
+mod input;
+mod modes;
+mod mouse;
+mod notify;
+mod paste;
+mod process;
 mod pty;
+mod quad;
+mod renderer;
+mod shell_integration;
 mod terminal;
 mod window;
 
@@ -26,18 +46,34 @@ pub fn configure_logger() {
         .init();
 }
 
+/// Directory given with `--working-directory`, where the shell is started
+fn working_directory() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--working-directory" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
 fn main() -> anyhow::Result<()> {
     configure_logger();
 
-    let session = PtySession::spawn()?;
-    let terminal = Terminal::new(session);
+    let config = Config::load();
+    let session = PtySession::spawn(&config, working_directory().as_deref())?;
+    let clipboard = clipboard::system_or_memory();
+    let notifier: SharedNotifier = Arc::new(Mutex::new(CommandNotifier::new(
+        config.notify_command.clone(),
+    )));
+    let terminal = Terminal::new(session, config.clone(), clipboard.clone(), notifier);
 
     let event_loop = EventLoop::new()?;
     let frame_interval = std::time::Duration::from_millis(16);
     event_loop.set_control_flow(ControlFlow::WaitUntil(
         std::time::Instant::now() + frame_interval,
     ));
-    event_loop.run_app(&mut Application::new(terminal))?;
+    event_loop.run_app(&mut Application::new(terminal, config, clipboard))?;
 
     Ok(())
 }
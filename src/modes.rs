@@ -0,0 +1,135 @@
+/// Modes set with SM/RM and DECSET/DECRST
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// IRM
+    Insert,
+    /// LNM, line feeds also return the carriage
+    LineFeedNewLine,
+    /// DECCKM, cursor keys send application sequences
+    CursorKeys,
+    /// DECSCNM, swaps the default foreground and background
+    ReverseVideo,
+    /// DECOM
+    Origin,
+    /// DECAWM
+    AutoWrap,
+    X10Mouse,
+    /// DECTCEM
+    ShowCursor,
+    AltScreen,
+    /// DECNKM, the keypad sends application sequences
+    KeypadApplication,
+    /// DECLRMM
+    LeftRightMargin,
+    NormalMouse,
+    ButtonEventMouse,
+    AnyEventMouse,
+    FocusEvents,
+    Utf8Mouse,
+    SgrMouse,
+    UrxvtMouse,
+    SgrPixelsMouse,
+    AltScreenClear,
+    SaveCursor,
+    AltScreenSaveCursor,
+    BracketedPaste,
+}
+
+impl Mode {
+    /// Modes with their number and whether they are DEC private modes
+    const NUMBERS: &[(Mode, u16, bool)] = &[
+        (Mode::Insert, 4, false),
+        (Mode::LineFeedNewLine, 20, false),
+        (Mode::CursorKeys, 1, true),
+        (Mode::ReverseVideo, 5, true),
+        (Mode::Origin, 6, true),
+        (Mode::AutoWrap, 7, true),
+        (Mode::X10Mouse, 9, true),
+        (Mode::ShowCursor, 25, true),
+        (Mode::AltScreen, 47, true),
+        (Mode::KeypadApplication, 66, true),
+        (Mode::LeftRightMargin, 69, true),
+        (Mode::NormalMouse, 1000, true),
+        (Mode::ButtonEventMouse, 1002, true),
+        (Mode::AnyEventMouse, 1003, true),
+        (Mode::FocusEvents, 1004, true),
+        (Mode::Utf8Mouse, 1005, true),
+        (Mode::SgrMouse, 1006, true),
+        (Mode::UrxvtMouse, 1015, true),
+        (Mode::SgrPixelsMouse, 1016, true),
+        (Mode::AltScreenClear, 1047, true),
+        (Mode::SaveCursor, 1048, true),
+        (Mode::AltScreenSaveCursor, 1049, true),
+        (Mode::BracketedPaste, 2004, true),
+    ];
+
+    /// Mouse tracking modes, of which only one is set at a time
+    pub const MOUSE_TRACKING: &[Mode] = &[
+        Mode::X10Mouse,
+        Mode::NormalMouse,
+        Mode::ButtonEventMouse,
+        Mode::AnyEventMouse,
+    ];
+    /// Mouse encodings, of which only one is set at a time
+    pub const MOUSE_ENCODINGS: &[Mode] = &[
+        Mode::Utf8Mouse,
+        Mode::SgrMouse,
+        Mode::UrxvtMouse,
+        Mode::SgrPixelsMouse,
+    ];
+
+    pub fn from_number(number: u16, private: bool) -> Option<Self> {
+        Self::NUMBERS
+            .iter()
+            .find(|(_, n, p)| *n == number && *p == private)
+            .map(|(mode, ..)| *mode)
+    }
+
+    fn bit(self) -> u64 {
+        1 << self as u64
+    }
+}
+
+/// The state of every mode, plus the values saved by XTSAVE
+#[derive(Clone, Copy, Debug)]
+pub struct Modes {
+    enabled: u64,
+    saved: u64,
+    /// Which modes have a saved value
+    saved_mask: u64,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            enabled: Mode::AutoWrap.bit() | Mode::ShowCursor.bit(),
+            saved: 0,
+            saved_mask: 0,
+        }
+    }
+}
+
+impl Modes {
+    pub fn get(&self, mode: Mode) -> bool {
+        self.enabled & mode.bit() != 0
+    }
+
+    pub fn set(&mut self, mode: Mode, enabled: bool) {
+        if enabled {
+            self.enabled |= mode.bit();
+        } else {
+            self.enabled &= !mode.bit();
+        }
+    }
+
+    /// XTSAVE
+    pub fn save(&mut self, mode: Mode) {
+        self.saved_mask |= mode.bit();
+        self.saved = (self.saved & !mode.bit()) | (self.enabled & mode.bit());
+    }
+
+    /// Returns the value saved by XTSAVE, if any
+    pub fn saved(&self, mode: Mode) -> Option<bool> {
+        (self.saved_mask & mode.bit() != 0).then_some(self.saved & mode.bit() != 0)
+    }
+}
@@ -0,0 +1,271 @@
+use winit::keyboard::ModifiersState;
+
+/// Mouse events reported to the program, set by DECSET 9, 1000, 1002 and
+/// 1003
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tracking {
+    /// X10, button presses only, without the modifiers
+    Press,
+    /// Presses and releases
+    Click,
+    /// Also motion while a button is held
+    Drag,
+    /// Also motion without any button
+    Motion,
+}
+
+/// How mouse events are reported, set by DECSET 1005, 1006, 1015 and 1016
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// `CSI M` and three bytes, limited to 223 columns and rows
+    Default,
+    /// `CSI M` and three characters in UTF-8, limited to 2015
+    Utf8,
+    /// `CSI < button ; x ; y M`, or `m` on release
+    Sgr,
+    /// `CSI button ; x ; y M`
+    Urxvt,
+    /// Like SGR, with coordinates in pixels
+    SgrPixels,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseProtocol {
+    pub tracking: Tracking,
+    pub encoding: Encoding,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+impl Button {
+    fn code(self) -> u32 {
+        match self {
+            Button::Left => 0,
+            Button::Middle => 1,
+            Button::Right => 2,
+            Button::WheelUp => 64,
+            Button::WheelDown => 65,
+            Button::WheelLeft => 66,
+            Button::WheelRight => 67,
+        }
+    }
+
+    fn is_wheel(self) -> bool {
+        self.code() >= 64
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(Button),
+    Release(Button),
+    /// Motion to another cell, with the button held if any
+    Motion(Option<Button>),
+}
+
+impl MouseProtocol {
+    /// Whether the action is reported with this tracking mode. The wheel
+    /// has no releases, and isn't reported at all in X10 mode.
+    pub fn reports(&self, action: MouseAction) -> bool {
+        match (self.tracking, action) {
+            (Tracking::Press, MouseAction::Press(button)) => !button.is_wheel(),
+            (_, MouseAction::Press(_)) => true,
+            (Tracking::Press, _) => false,
+            (_, MouseAction::Release(button)) => !button.is_wheel(),
+            (Tracking::Motion, MouseAction::Motion(_)) => true,
+            (Tracking::Drag, MouseAction::Motion(button)) => button.is_some(),
+            (Tracking::Click, MouseAction::Motion(_)) => false,
+        }
+    }
+
+    /// Returns the report of an action at a cell, or at a position in
+    /// pixels from the top left corner of the grid with the SGR-Pixels
+    /// encoding. `None` when the action isn't reported, or its position
+    /// can't be encoded.
+    pub fn encode(
+        &self,
+        action: MouseAction,
+        modifiers: ModifiersState,
+        cell: (usize, usize),
+        pixels: (usize, usize),
+    ) -> Option<Vec<u8>> {
+        if !self.reports(action) {
+            return None;
+        }
+        let sgr = matches!(self.encoding, Encoding::Sgr | Encoding::SgrPixels);
+        let mut code = match action {
+            MouseAction::Press(button) | MouseAction::Motion(Some(button)) => button.code(),
+            // SGR tells which button was released, the others don't
+            MouseAction::Release(button) if sgr => button.code(),
+            MouseAction::Release(_) | MouseAction::Motion(None) => 3,
+        };
+        if let MouseAction::Motion(_) = action {
+            code += 32;
+        }
+        if self.tracking != Tracking::Press {
+            if modifiers.shift_key() {
+                code += 4;
+            }
+            if modifiers.alt_key() {
+                code += 8;
+            }
+            if modifiers.control_key() {
+                code += 16;
+            }
+        }
+
+        let (x, y) = match self.encoding {
+            Encoding::SgrPixels => pixels,
+            _ => cell,
+        };
+        let (x, y) = (x as u32 + 1, y as u32 + 1);
+        let report = match self.encoding {
+            Encoding::Default => {
+                let byte = |value: u32| u8::try_from(value + 32).ok();
+                return Some(vec![0x1b, b'[', b'M', byte(code)?, byte(x)?, byte(y)?]);
+            }
+            Encoding::Utf8 => {
+                // Two byte characters go up to 2047
+                let char = |value: u32| char::from_u32(value + 32).filter(|c| c.len_utf8() <= 2);
+                format!("\x1b[M{}{}{}", char(code)?, char(x)?, char(y)?)
+            }
+            Encoding::Sgr | Encoding::SgrPixels => {
+                let end = match action {
+                    MouseAction::Release(_) => 'm',
+                    _ => 'M',
+                };
+                format!("\x1b[<{};{};{}{}", code, x, y, end)
+            }
+            Encoding::Urxvt => format!("\x1b[{};{};{}M", code + 32, x, y),
+        };
+        Some(report.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: ModifiersState = ModifiersState::empty();
+
+    fn report(
+        tracking: Tracking,
+        encoding: Encoding,
+        action: MouseAction,
+        modifiers: ModifiersState,
+        cell: (usize, usize),
+    ) -> Option<Vec<u8>> {
+        let protocol = MouseProtocol { tracking, encoding };
+        protocol.encode(action, modifiers, cell, (cell.0 * 8, cell.1 * 16))
+    }
+
+    #[test]
+    fn reported_actions() {
+        let left = MouseAction::Press(Button::Left);
+        let release = MouseAction::Release(Button::Left);
+        let wheel = MouseAction::Press(Button::WheelUp);
+        let drag = MouseAction::Motion(Some(Button::Left));
+        let motion = MouseAction::Motion(None);
+        let cases = [
+            (Tracking::Press, [true, false, false, false, false]),
+            (Tracking::Click, [true, true, true, false, false]),
+            (Tracking::Drag, [true, true, true, true, false]),
+            (Tracking::Motion, [true, true, true, true, true]),
+        ];
+        for (tracking, expected) in cases {
+            let protocol = MouseProtocol {
+                tracking,
+                encoding: Encoding::Sgr,
+            };
+            let reported = [left, release, wheel, drag, motion].map(|a| protocol.reports(a));
+            assert_eq!(reported, expected, "{:?}", tracking);
+        }
+        // Wheel notches have no release
+        let protocol = MouseProtocol {
+            tracking: Tracking::Motion,
+            encoding: Encoding::Sgr,
+        };
+        assert!(!protocol.reports(MouseAction::Release(Button::WheelDown)));
+    }
+
+    #[test]
+    fn x10() {
+        let ctrl = ModifiersState::CONTROL;
+        let press = MouseAction::Press(Button::Right);
+        // X10 doesn't report the modifiers
+        assert_eq!(
+            report(Tracking::Press, Encoding::Default, press, ctrl, (0, 2)).as_deref(),
+            Some(&b"\x1b[M\"!#"[..])
+        );
+        assert_eq!(
+            report(Tracking::Click, Encoding::Default, press, ctrl, (0, 2)).as_deref(),
+            Some(&b"\x1b[M2!#"[..])
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        use Encoding::*;
+        let press = MouseAction::Press(Button::Left);
+        let release = MouseAction::Release(Button::Middle);
+        let wheel = MouseAction::Press(Button::WheelDown);
+        let drag = MouseAction::Motion(Some(Button::Left));
+        let motion = MouseAction::Motion(None);
+        let shift_alt = ModifiersState::SHIFT | ModifiersState::ALT;
+        let cases = [
+            (Default, press, NONE, (0, 0), "\x1b[M !!"),
+            (Default, release, NONE, (1, 2), "\x1b[M#\"#"),
+            (Default, wheel, NONE, (0, 0), "\x1b[Ma!!"),
+            (Default, drag, shift_alt, (0, 0), "\x1b[ML!!"),
+            (Default, motion, NONE, (0, 0), "\x1b[MC!!"),
+            (Utf8, press, NONE, (300, 0), "\x1b[M \u{14d}!"),
+            (Sgr, press, NONE, (0, 0), "\x1b[<0;1;1M"),
+            (Sgr, release, NONE, (299, 499), "\x1b[<1;300;500m"),
+            (Sgr, wheel, shift_alt, (0, 0), "\x1b[<77;1;1M"),
+            (Sgr, motion, NONE, (4, 5), "\x1b[<35;5;6M"),
+            (Urxvt, press, NONE, (0, 0), "\x1b[32;1;1M"),
+            (Urxvt, release, NONE, (299, 0), "\x1b[35;300;1M"),
+            (SgrPixels, press, NONE, (2, 3), "\x1b[<0;17;49M"),
+            (SgrPixels, release, NONE, (2, 3), "\x1b[<1;17;49m"),
+        ];
+        for (encoding, action, modifiers, cell, expected) in cases {
+            let report = report(Tracking::Motion, encoding, action, modifiers, cell);
+            assert_eq!(
+                report.as_deref(),
+                Some(expected.as_bytes()),
+                "{:?} {:?}",
+                encoding,
+                action
+            );
+        }
+    }
+
+    #[test]
+    fn coordinate_limits() {
+        let press = MouseAction::Press(Button::Left);
+        let at = |encoding, x| report(Tracking::Click, encoding, press, NONE, (x, 0));
+        // A byte goes up to 255, so column 223
+        assert_eq!(
+            at(Encoding::Default, 222).as_deref(),
+            Some(&b"\x1b[M \xff!"[..])
+        );
+        assert_eq!(at(Encoding::Default, 223), None);
+        // Two bytes of UTF-8 go up to 2047, so column 2015
+        let utf8 = "\x1b[M \u{7ff}!".as_bytes();
+        assert_eq!(at(Encoding::Utf8, 2014).as_deref(), Some(utf8));
+        assert_eq!(at(Encoding::Utf8, 2015), None);
+        let sgr = b"\x1b[<0;2016;1M";
+        assert_eq!(at(Encoding::Sgr, 2015).as_deref(), Some(&sgr[..]));
+        let urxvt = b"\x1b[32;2016;1M";
+        assert_eq!(at(Encoding::Urxvt, 2015).as_deref(), Some(&urxvt[..]));
+    }
+}
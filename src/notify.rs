@@ -0,0 +1,104 @@
+use crate::process::spawn_detached;
+use anyhow::Context;
+use anyhow::Result;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Notifier shared by the terminal and whoever created it
+pub type SharedNotifier = Arc<Mutex<dyn Notifier>>;
+
+/// A desktop notification requested by a program
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+pub trait Notifier: Send {
+    fn notify(&mut self, notification: &Notification) -> Result<()>;
+}
+
+/// Shows notifications by running a command such as `notify-send`, with the
+/// title and the body as its last two arguments, after `--`
+pub struct CommandNotifier {
+    command: String,
+}
+
+impl CommandNotifier {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    /// Returns the command showing a notification, if one is configured.
+    /// The title and the body come from the program in the terminal, so
+    /// they follow `--` to never be taken for options.
+    fn command(&self, notification: &Notification) -> Option<Command> {
+        let mut args = self.command.split_whitespace();
+        let program = args.next()?;
+        let title = match notification.title.as_str() {
+            "" => env!("CARGO_PKG_NAME"),
+            title => title,
+        };
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .arg("--")
+            .arg(title)
+            .arg(&notification.body);
+        Some(command)
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, notification: &Notification) -> Result<()> {
+        let Some(command) = self.command(notification) else {
+            return Ok(());
+        };
+        let program = command.get_program().to_string_lossy().into_owned();
+        spawn_detached(command).with_context(|| format!("Failed to run {}", program))
+    }
+}
+
+/// Records notifications instead of showing them, so they can be checked
+/// without a desktop
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryNotifier {
+    pub notifications: Vec<Notification>,
+}
+
+#[cfg(test)]
+impl Notifier for MemoryNotifier {
+    fn notify(&mut self, notification: &Notification) -> Result<()> {
+        self.notifications.push(notification.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_in_notifications() {
+        let notifier = CommandNotifier::new("notify-send -a cosmicterm".to_string());
+        let notification = Notification {
+            title: "-u critical".to_string(),
+            body: "--action=run".to_string(),
+        };
+        let command = notifier.command(&notification).unwrap();
+        assert_eq!(command.get_program(), "notify-send");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["-a", "cosmicterm", "--", "-u critical", "--action=run"]
+        );
+
+        assert!(
+            CommandNotifier::new(String::new())
+                .command(&notification)
+                .is_none()
+        );
+    }
+}
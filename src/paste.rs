@@ -0,0 +1,180 @@
+use crate::grid::Attributes;
+use crate::grid::Cell;
+use crate::grid::Flags;
+use crate::grid::Row;
+use unicode_width::UnicodeWidthChar;
+
+/// Programs that download scripts, and shells that would run them when
+/// piped into
+const DOWNLOADERS: &[&str] = &["curl", "wget"];
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "fish", "dash", "ksh"];
+
+/// Characters that end a word in a command line besides whitespace
+const METACHARACTERS: &[char] = &[';', '&', '|', '(', ')', '$', '`'];
+
+/// Lines of the pasted text shown in the confirmation
+const PREVIEW_LINES: usize = 3;
+
+/// Removes the controls other than tabs and newlines, which could end a
+/// bracketed paste early, send key sequences or edit the command line, and
+/// turns newlines into carriage returns as sent by the Enter key
+pub fn sanitize(text: &str) -> String {
+    text.replace("\r\n", "\r")
+        .chars()
+        .filter(|&c| !c.is_control() || matches!(c, '\t' | '\r' | '\n'))
+        .map(|c| if c == '\n' { '\r' } else { c })
+        .collect()
+}
+
+/// Bytes sent to the program for sanitized text, between `CSI 200 ~` and
+/// `CSI 201 ~` when bracketed paste mode is set
+pub fn encode(text: &str, bracketed: bool) -> Vec<u8> {
+    if bracketed {
+        format!("\x1b[200~{}\x1b[201~", text).into_bytes()
+    } else {
+        text.as_bytes().to_vec()
+    }
+}
+
+/// Whether sanitized text should be confirmed before it is pasted into a
+/// shell that would run it right away: it has several lines, or runs
+/// `sudo` or a downloaded script
+pub fn needs_confirmation(text: &str) -> bool {
+    text.contains('\r')
+        || words(text).any(|word| word == "sudo")
+        || text.split(['\r', ';', '&']).any(pipes_download_to_shell)
+}
+
+/// The words of a command line, split at whitespace and shell
+/// metacharacters
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| c.is_whitespace() || METACHARACTERS.contains(&c))
+        .filter(|word| !word.is_empty())
+}
+
+/// Whether a pipeline pipes the output of `curl` or `wget` into a shell
+fn pipes_download_to_shell(pipeline: &str) -> bool {
+    let programs: Vec<&str> = pipeline
+        .split('|')
+        .map(|command| words(command).next().unwrap_or_default())
+        .map(|program| program.rsplit('/').next().unwrap_or_default())
+        .collect();
+    programs
+        .iter()
+        .position(|program| DOWNLOADERS.contains(program))
+        .is_some_and(|i| programs[i + 1..].iter().any(|p| SHELLS.contains(p)))
+}
+
+/// Draws the confirmation of a paste over the bottom rows of the screen
+pub fn draw_confirmation(rows: &mut [Row], text: &str) {
+    let Some(cols) = rows.first().map(|row| row.cells().len()) else {
+        return;
+    };
+    let lines: Vec<&str> = text.trim_end_matches('\r').split('\r').collect();
+    let mut overlay = vec![format!(
+        "Paste {} line{}? Enter to paste, Escape to cancel",
+        lines.len(),
+        if lines.len() == 1 { "" } else { "s" }
+    )];
+    overlay.extend(
+        lines
+            .iter()
+            .take(PREVIEW_LINES)
+            .map(|line| format!("> {}", line)),
+    );
+    if lines.len() > PREVIEW_LINES {
+        overlay.push("> ...".to_string());
+    }
+
+    let attrs = Attributes {
+        flags: Flags::INVERSE,
+        ..Default::default()
+    };
+    let start = rows.len().saturating_sub(overlay.len());
+    for (row, line) in rows[start..].iter_mut().zip(&overlay) {
+        *row = overlay_row(line, cols, &attrs);
+    }
+}
+
+/// A row showing `text`, cut to `cols` columns
+fn overlay_row(text: &str, cols: usize, attrs: &Attributes) -> Row {
+    let mut row = Row::new(cols, &Cell::blank(attrs.clone()));
+    let mut x = 0;
+    for c in text.chars() {
+        let c = if c.is_control() { ' ' } else { c };
+        let Some(width) = c.width().filter(|&width| width > 0) else {
+            continue;
+        };
+        if x + width > cols {
+            break;
+        }
+        row[x] = Cell {
+            c,
+            attrs: attrs.clone(),
+            width: width as u8,
+        };
+        // Wide characters are followed by spacers
+        for spacer in x + 1..x + width {
+            row[spacer] = Cell {
+                c: ' ',
+                attrs: attrs.clone(),
+                width: 0,
+            };
+        }
+        x += width;
+    }
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized() {
+        assert_eq!(sanitize("ls\x1b[31m -la"), "ls[31m -la");
+        assert_eq!(sanitize("a\u{9b}31mb\u{85}c"), "a31mbc");
+        // Newlines become carriage returns, as sent by Enter
+        assert_eq!(sanitize("a\r\nb\nc\rd"), "a\rb\rc\rd");
+        // Other controls could interrupt or edit the command line
+        assert_eq!(sanitize("a\tb\x03\x15\x7fc"), "a\tbc");
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        // The end of the paste can't be sent early, so the rest isn't run
+        let text = sanitize("x\x1b[201~; rm -rf ~\n");
+        assert_eq!(encode(&text, true), b"\x1b[200~x[201~; rm -rf ~\r\x1b[201~");
+        // Without bracketed paste the text is sent as is
+        assert_eq!(encode(&text, false), b"x[201~; rm -rf ~\r");
+        assert_eq!(encode("", false), b"");
+    }
+
+    #[test]
+    fn confirmation() {
+        assert!(!needs_confirmation("ls -la"));
+        assert!(!needs_confirmation("echo pseudo sudoers"));
+        assert!(needs_confirmation("ls\rpwd"));
+
+        assert!(needs_confirmation("sudo rm -rf /"));
+        assert!(needs_confirmation("true;sudo reboot"));
+        assert!(needs_confirmation("make&&sudo make install"));
+        assert!(needs_confirmation("false||sudo reboot"));
+        assert!(needs_confirmation("(sudo reboot)"));
+        assert!(needs_confirmation("echo $(sudo cat /etc/shadow)"));
+        assert!(needs_confirmation("echo `sudo id`"));
+
+        assert!(!needs_confirmation(
+            "curl -O https://example.com/install.sh"
+        ));
+        assert!(needs_confirmation("curl -fsSL https://example.com | sh"));
+        assert!(needs_confirmation(
+            "wget -qO- https://example.com|/bin/bash -s"
+        ));
+        assert!(needs_confirmation(
+            "cd /tmp;curl https://example.com | sudo bash"
+        ));
+        assert!(needs_confirmation("(curl https://example.com)|sh"));
+        assert!(!needs_confirmation("curl https://example.com; sh build.sh"));
+    }
+}
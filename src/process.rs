@@ -0,0 +1,10 @@
+use std::io;
+use std::process::Command;
+
+/// Runs a command in the background. It is waited for on another thread so
+/// it doesn't linger as a zombie once it exits.
+pub fn spawn_detached(mut command: Command) -> io::Result<()> {
+    let mut child = command.spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use crate::config::Config;
+use crate::shell_integration;
 use anyhow::Result;
 use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
@@ -9,6 +11,7 @@ use portable_pty::PtySize;
 use portable_pty::PtySystem;
 use std::io::BufReader;
 use std::io::Read;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
@@ -21,8 +24,9 @@ pub struct PtySession {
 }
 
 impl PtySession {
-    pub fn spawn() -> Result<Self> {
-        let inner = Session::spawn()?;
+    /// Spawns the shell, in `cwd` when given
+    pub fn spawn(config: &Config, cwd: Option<&Path>) -> Result<Self> {
+        let inner = Session::spawn(config, cwd)?;
         let reader = inner.receiver.clone();
         let writer = inner.sender.clone();
         Ok(Self {
@@ -40,7 +44,7 @@ impl PtySession {
         self.writer.clone()
     }
 
-    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+    pub fn resize(&self, cols: u16, rows: u16, pixel_width: u16, pixel_height: u16) -> Result<()> {
         self._session
             .lock()
             .expect("Failed to lock PTY session")
@@ -48,7 +52,8 @@ impl PtySession {
             .resize(PtySize {
                 rows,
                 cols,
-                ..Default::default()
+                pixel_width,
+                pixel_height,
             })
     }
 }
@@ -79,7 +84,7 @@ impl Session {
     ];
 
     /// Spawns the shell inside a PTY and returns a receiver for its output
-    fn spawn() -> Result<Self> {
+    fn spawn(config: &Config, cwd: Option<&Path>) -> Result<Self> {
         let shell = get_shell();
         tracing::info!("Spawning shell: {}", shell);
 
@@ -91,10 +96,19 @@ impl Session {
             pixel_height: 0,
         })?;
 
-        let mut command = CommandBuilder::new(shell);
+        let mut command = CommandBuilder::new(&shell);
         for (key, value) in Self::DEFAULT_ENV {
             command.env(key, value);
         }
+        if config.shell_integration
+            && let Err(e) = shell_integration::inject(&mut command, &shell)
+        {
+            tracing::warn!("Failed to set up the shell integration: {:#}", e);
+        }
+        if let Some(cwd) = cwd {
+            tracing::info!("Starting in {}", cwd.display());
+            command.cwd(cwd);
+        }
         let child = pair.slave.spawn_command(command)?;
 
         let (reader_tx, reader_rx): (Sender<String>, Receiver<String>) =
@@ -0,0 +1,164 @@
+use crate::color::Rgb;
+use wgpu::BlendState;
+use wgpu::Buffer;
+use wgpu::BufferDescriptor;
+use wgpu::BufferUsages;
+use wgpu::ColorTargetState;
+use wgpu::ColorWrites;
+use wgpu::Device;
+use wgpu::FragmentState;
+use wgpu::MultisampleState;
+use wgpu::PipelineLayoutDescriptor;
+use wgpu::PrimitiveState;
+use wgpu::Queue;
+use wgpu::RenderPass;
+use wgpu::RenderPipeline;
+use wgpu::RenderPipelineDescriptor;
+use wgpu::ShaderModuleDescriptor;
+use wgpu::ShaderSource;
+use wgpu::TextureFormat;
+use wgpu::VertexBufferLayout;
+use wgpu::VertexState;
+use wgpu::VertexStepMode;
+
+/// A solid rectangle, in physical pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Quad {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub color: Rgb,
+    pub alpha: f32,
+}
+
+impl Quad {
+    pub fn new(x: f32, y: f32, width: f32, height: f32, color: Rgb) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            color,
+            alpha: 1.0,
+        }
+    }
+}
+
+/// Draws solid rectangles, used for cell backgrounds and text decorations
+pub struct QuadRenderer {
+    pipeline: RenderPipeline,
+    vertex_buffer: Buffer,
+    vertex_count: u32,
+}
+
+impl QuadRenderer {
+    /// Two floats for the position and four for the color
+    const VERTEX_SIZE: u64 = 6 * 4;
+
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("quad shader"),
+            source: ShaderSource::Wgsl(include_str!("quad.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("quad pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("quad pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[VertexBufferLayout {
+                    array_stride: Self::VERTEX_SIZE,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer: Self::create_buffer(device, 0),
+            vertex_count: 0,
+        }
+    }
+
+    fn create_buffer(device: &Device, vertices: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("quad vertices"),
+            // Start with room for a full screen of cell backgrounds
+            size: (vertices.max(6 * 80 * 24) * Self::VERTEX_SIZE),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads the quads to draw, for a surface of `width`×`height` pixels
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        quads: &[Quad],
+        width: u32,
+        height: u32,
+    ) {
+        let (width, height) = (width as f32, height as f32);
+        let mut data = Vec::with_capacity(quads.len() * 6 * Self::VERTEX_SIZE as usize);
+        for quad in quads {
+            // Convert from pixels to normalized device coordinates
+            let left = quad.x / width * 2.0 - 1.0;
+            let right = (quad.x + quad.width) / width * 2.0 - 1.0;
+            let top = 1.0 - quad.y / height * 2.0;
+            let bottom = 1.0 - (quad.y + quad.height) / height * 2.0;
+            let color = quad.color.to_linear(quad.alpha);
+            for (x, y) in [
+                (left, top),
+                (left, bottom),
+                (right, top),
+                (right, top),
+                (left, bottom),
+                (right, bottom),
+            ] {
+                for value in [x, y].iter().chain(&color) {
+                    data.extend_from_slice(&value.to_ne_bytes());
+                }
+            }
+        }
+
+        let vertices = (quads.len() * 6) as u64;
+        if vertices * Self::VERTEX_SIZE > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_buffer(device, vertices.next_power_of_two());
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, &data);
+        self.vertex_count = vertices as u32;
+    }
+
+    pub fn render(&self, pass: &mut RenderPass<'_>) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.vertex_count, 0..1);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::color;
+use crate::color::Color;
+use crate::color::DEFAULT_BACKGROUND;
+use crate::color::DEFAULT_FOREGROUND;
+use crate::color::Rgb;
+use crate::grid::Attributes;
+use crate::grid::Flags;
+use crate::grid::Hyperlink;
+use crate::grid::Row;
+use crate::quad::Quad;
+use crate::terminal::Progress;
+use glyphon::Attrs;
+use glyphon::Buffer;
+use glyphon::Family;
+use glyphon::FontSystem;
+use glyphon::Metrics;
+use glyphon::Shaping;
+use glyphon::Style;
+use glyphon::Weight;
+use glyphon::Wrap;
+use std::sync::Arc;
+
+/// Space between the edge of the window and the grid, in physical pixels
+pub const PADDING: f32 = 10.0;
+
+/// Thickness of the progress bar, in logical pixels
+const PROGRESS_HEIGHT: f32 = 3.0;
+
+/// Size of a cell in logical pixels
+#[derive(Clone, Copy, Debug)]
+pub struct CellMetrics {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CellMetrics {
+    pub const FONT: Metrics = Metrics::new(16.0, 16.0 * 1.2);
+
+    pub fn measure(font_system: &mut FontSystem) -> Self {
+        let mut buffer = Buffer::new_empty(Self::FONT);
+        buffer.set_wrap(font_system, Wrap::None);
+
+        // Use size of space to determine cell size
+        buffer.set_text(
+            font_system,
+            " ",
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Advanced,
+        );
+        let width = buffer
+            .line_layout(font_system, 0)
+            .and_then(|layout| layout.first().map(|line| line.w))
+            .unwrap_or(Self::FONT.font_size * 0.6);
+        Self {
+            width,
+            height: Self::FONT.line_height,
+        }
+    }
+}
+
+/// Default foreground and background colors, which are swapped in reverse
+/// video mode (DECSCNM)
+pub fn default_colors(reverse_video: bool) -> (Rgb, Rgb) {
+    if reverse_video {
+        (DEFAULT_BACKGROUND, DEFAULT_FOREGROUND)
+    } else {
+        (DEFAULT_FOREGROUND, DEFAULT_BACKGROUND)
+    }
+}
+
+/// Foreground and background colors of a cell once its attributes are
+/// applied
+pub fn cell_colors(attrs: &Attributes, reverse_video: bool) -> (Rgb, Rgb) {
+    let (default_fg, default_bg) = default_colors(reverse_video);
+    let fg = match attrs.fg {
+        // Bold text uses the bright variant of the ANSI colors
+        Color::Indexed(index) if index < 8 && attrs.flags.contains(Flags::BOLD) => {
+            color::indexed(index + 8)
+        }
+        fg => fg.resolve(default_fg),
+    };
+    let bg = attrs.bg.resolve(default_bg);
+    let (mut fg, bg) = if attrs.flags.contains(Flags::INVERSE) {
+        (bg, fg)
+    } else {
+        (fg, bg)
+    };
+    if attrs.flags.contains(Flags::DIM) {
+        fg = fg.blend(bg, 0.4);
+    }
+    if attrs.flags.contains(Flags::HIDDEN) {
+        fg = bg;
+    }
+    (fg, bg)
+}
+
+/// Shaped text of one row of the grid. The text is only reshaped when the
+/// row changes.
+pub struct RowBuffer {
+    pub buffer: Buffer,
+    content: Option<(Row, Option<usize>, bool)>,
+}
+
+impl RowBuffer {
+    pub fn new(font_system: &mut FontSystem) -> Self {
+        let mut buffer = Buffer::new(font_system, CellMetrics::FONT);
+        buffer.set_wrap(font_system, Wrap::None);
+        Self {
+            buffer,
+            content: None,
+        }
+    }
+
+    /// Sets the text of the row, `cursor` being the column of the cursor
+    /// when it is on this row
+    pub fn update(
+        &mut self,
+        font_system: &mut FontSystem,
+        row: &Row,
+        cursor: Option<usize>,
+        reverse_video: bool,
+    ) {
+        if self
+            .content
+            .as_ref()
+            .is_some_and(|(current, current_cursor, reverse)| {
+                current == row && *current_cursor == cursor && *reverse == reverse_video
+            })
+        {
+            return;
+        }
+
+        let mut spans: Vec<(String, Attrs)> = Vec::new();
+        for (x, cell) in row.cells().iter().enumerate() {
+            if cell.is_spacer() {
+                continue;
+            }
+            let (fg, bg) = cell_colors(&cell.attrs, reverse_video);
+            // The cursor is drawn as a block with the colors swapped
+            let color = if cursor == Some(x) { bg } else { fg };
+            let mut attrs = Attrs::new()
+                .family(Family::Monospace)
+                .color(glyphon::Color::rgb(color.r, color.g, color.b));
+            if cell.attrs.flags.contains(Flags::BOLD) {
+                attrs = attrs.weight(Weight::BOLD);
+            }
+            if cell.attrs.flags.contains(Flags::ITALIC) {
+                attrs = attrs.style(Style::Italic);
+            }
+            let c = if cell.attrs.flags.contains(Flags::HIDDEN) {
+                ' '
+            } else {
+                cell.c
+            };
+
+            match spans.last_mut() {
+                Some((text, last)) if *last == attrs => text.push(c),
+                _ => spans.push((c.to_string(), attrs)),
+            }
+        }
+
+        self.buffer.set_rich_text(
+            font_system,
+            spans
+                .iter()
+                .map(|(text, attrs)| (text.as_str(), attrs.clone())),
+            &Attrs::new().family(Family::Monospace),
+            Shaping::Advanced,
+            None,
+        );
+        self.content = Some((row.clone(), cursor, reverse_video));
+    }
+}
+
+/// Backgrounds, text decorations and the cursor of the visible rows, in
+/// physical pixels
+pub fn grid_quads(
+    rows: &[Row],
+    cursor: Option<(usize, usize)>,
+    reverse_video: bool,
+    hovered_link: Option<&Arc<Hyperlink>>,
+    cell: CellMetrics,
+    scale: f32,
+) -> Vec<Quad> {
+    let (_, default_bg) = default_colors(reverse_video);
+    let cell_width = cell.width * scale;
+    let cell_height = cell.height * scale;
+    let thickness = scale.round().max(1.0);
+    let mut quads = Vec::new();
+    // Decorations are drawn on top of the backgrounds
+    let mut decorations = Vec::new();
+
+    for (y, row) in rows.iter().enumerate() {
+        let top = PADDING + y as f32 * cell_height;
+        let mut backgrounds = Vec::with_capacity(row.cells().len());
+        for (x, cell) in row.cells().iter().enumerate() {
+            let (fg, bg) = cell_colors(&cell.attrs, reverse_video);
+            let left = PADDING + x as f32 * cell_width;
+            let on_cursor = match cursor {
+                Some((cursor_x, cursor_y)) if cursor_y == y => {
+                    cursor_x == x || (cell.is_spacer() && cursor_x + 1 == x)
+                }
+                _ => false,
+            };
+            backgrounds.push(if on_cursor { fg } else { bg });
+
+            let flags = cell.attrs.flags;
+            // Links are underlined while the mouse is over them
+            let hovered = match (hovered_link, &cell.attrs.hyperlink) {
+                (Some(hovered), Some(link)) => Hyperlink::same_link(hovered, link),
+                _ => false,
+            };
+            if hovered || flags.intersects(Flags::UNDERLINE | Flags::DOUBLE_UNDERLINE) {
+                let y = top + cell_height - 2.0 * thickness;
+                decorations.push(Quad::new(left, y, cell_width, thickness, fg));
+                if flags.contains(Flags::DOUBLE_UNDERLINE) {
+                    let y = y - 2.0 * thickness;
+                    decorations.push(Quad::new(left, y, cell_width, thickness, fg));
+                }
+            }
+            if flags.contains(Flags::STRIKEOUT) {
+                let y = top + (cell_height / 2.0).round();
+                decorations.push(Quad::new(left, y, cell_width, thickness, fg));
+            }
+        }
+
+        // Commands that failed are flagged in the padding, on the line where
+        // they ended
+        if row.exit_code().is_some_and(|code| code != 0) {
+            let width = (PADDING / 2.0).round();
+            quads.push(Quad::new(0.0, top, width, cell_height, color::indexed(1)));
+        }
+
+        // Backgrounds are drawn in runs of the same color
+        let mut start = 0;
+        for run in backgrounds.chunk_by(|a, b| a == b) {
+            if run[0] != default_bg {
+                quads.push(Quad::new(
+                    PADDING + start as f32 * cell_width,
+                    top,
+                    run.len() as f32 * cell_width,
+                    cell_height,
+                    run[0],
+                ));
+            }
+            start += run.len();
+        }
+    }
+
+    quads.extend(decorations);
+    quads
+}
+
+/// Returns the bar showing the progress reported with OSC 9;4, along the top
+/// edge of a window `width` physical pixels wide
+pub fn progress_quad(progress: Progress, width: f32, scale: f32) -> Option<Quad> {
+    let (fraction, color) = match progress {
+        Progress::None => return None,
+        Progress::Normal(percent) => (percent as f32 / 100.0, color::indexed(2)),
+        Progress::Error(percent) => (percent as f32 / 100.0, color::indexed(1)),
+        Progress::Paused(percent) => (percent as f32 / 100.0, color::indexed(3)),
+        // There's no percentage, the whole edge is filled
+        Progress::Indeterminate => (1.0, color::indexed(4)),
+    };
+    let height = (PROGRESS_HEIGHT * scale).round();
+    Some(Quad::new(
+        0.0,
+        0.0,
+        (width * fraction).round(),
+        height,
+        color,
+    ))
+}
+
+/// Returns the cell under a point of the window, given in physical pixels
+pub fn cell_at(x: f64, y: f64, cell: CellMetrics, scale: f32) -> Option<(usize, usize)> {
+    let x = (x as f32 - PADDING) / (cell.width * scale);
+    let y = (y as f32 - PADDING) / (cell.height * scale);
+    (x >= 0.0 && y >= 0.0).then_some((x as usize, y as usize))
+}
@@ -0,0 +1,123 @@
+use anyhow::Context;
+use anyhow::Result;
+use portable_pty::CommandBuilder;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Scripts that mark prompts (OSC 133) and report the current directory
+/// (OSC 7), with their path relative to the integration directory
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "bash/cosmicterm.bash",
+        include_str!("../shell-integration/bash/cosmicterm.bash"),
+    ),
+    (
+        "zsh/.zshenv",
+        include_str!("../shell-integration/zsh/.zshenv"),
+    ),
+    (
+        "zsh/cosmicterm.zsh",
+        include_str!("../shell-integration/zsh/cosmicterm.zsh"),
+    ),
+    (
+        "fish/vendor_conf.d/cosmicterm.fish",
+        include_str!("../shell-integration/fish/vendor_conf.d/cosmicterm.fish"),
+    ),
+];
+
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Makes the shell load its integration script, without touching the
+/// user's startup files. Shells other than bash, zsh and fish are left as
+/// they are.
+pub fn inject(command: &mut CommandBuilder, shell: &str) -> Result<()> {
+    let name = Path::new(shell)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let shell = match name {
+        "bash" => Shell::Bash,
+        "zsh" => Shell::Zsh,
+        "fish" => Shell::Fish,
+        _ => {
+            tracing::info!("No shell integration for {}", shell);
+            return Ok(());
+        }
+    };
+
+    let dir = write_scripts()?;
+    match shell {
+        // The script sources ~/.bashrc itself
+        Shell::Bash => {
+            command.arg("--rcfile");
+            command.arg(dir.join("bash/cosmicterm.bash"));
+        }
+        // Our .zshenv restores ZDOTDIR, so zsh goes on with the user's files
+        Shell::Zsh => {
+            if let Some(zdotdir) = std::env::var_os("ZDOTDIR") {
+                command.env("COSMICTERM_ORIG_ZDOTDIR", zdotdir);
+            }
+            command.env("ZDOTDIR", dir.join("zsh"));
+        }
+        // fish runs the vendor_conf.d scripts of every data directory
+        Shell::Fish => {
+            let data_dirs = std::env::var_os("XDG_DATA_DIRS");
+            let defaults = [
+                PathBuf::from("/usr/local/share"),
+                PathBuf::from("/usr/share"),
+            ];
+            let mut dirs = vec![dir.clone()];
+            match &data_dirs {
+                Some(data_dirs) => {
+                    command.env("COSMICTERM_ORIG_XDG_DATA_DIRS", data_dirs);
+                    dirs.extend(std::env::split_paths(data_dirs));
+                }
+                None => dirs.extend(defaults),
+            }
+            command.env("XDG_DATA_DIRS", std::env::join_paths(dirs)?);
+        }
+    }
+    tracing::info!(
+        "Loading shell integration for {} from {}",
+        name,
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Writes the scripts to the integration directory and returns it
+fn write_scripts() -> Result<PathBuf> {
+    let dir = integration_dir().context("No directory for the shell integration")?;
+    for (path, script) in SCRIPTS {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, script)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(dir)
+}
+
+/// A directory only the user can write to, as the scripts are run by the
+/// shell: `$XDG_RUNTIME_DIR`, or the cache directory
+fn integration_dir() -> Option<PathBuf> {
+    let absolute_var = |name| {
+        std::env::var_os(name)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    absolute_var("XDG_RUNTIME_DIR")
+        .or_else(|| absolute_var("XDG_CACHE_HOME"))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| {
+            dir.join("cosmicterm")
+                .join(env!("CARGO_PKG_VERSION"))
+                .join("shell-integration")
+        })
+}
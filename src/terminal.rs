@@ -1,60 +1,307 @@
+use crate::charset::Charset;
+use crate::charset::Charsets;
+use crate::clipboard::Selection;
+use crate::clipboard::SharedClipboard;
+use crate::color::Color;
+use crate::color::Rgb;
+use crate::config::Config;
+use crate::grid::Attributes;
+use crate::grid::Cell;
+use crate::grid::Flags;
+use crate::grid::Grid;
+use crate::grid::Hyperlink;
+use crate::grid::Marks;
+use crate::grid::Row;
+use crate::input::KeyboardFlags;
+use crate::input::KeyboardFlagsStack;
+use crate::modes::Mode;
+use crate::modes::Modes;
+use crate::mouse::Encoding;
+use crate::mouse::MouseProtocol;
+use crate::mouse::Tracking;
+use crate::notify::Notification;
+use crate::notify::SharedNotifier;
 use crate::pty::PtySession;
 use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use crossbeam_channel::Receiver;
-use std::cmp::max;
-use std::collections::VecDeque;
+use crossbeam_channel::Sender;
+use percent_encoding::percent_decode;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use unicode_width::UnicodeWidthChar;
 use vte::Params;
 use vte::Parser;
 use vte::Perform;
 
+/// Requests from the program running in the terminal that the application
+/// has to carry out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TerminalEvent {
+    Title(String),
+    Minimize(bool),
+    Move {
+        x: i32,
+        y: i32,
+    },
+    /// Resizes the text area to a size in pixels. A zero keeps the current
+    /// width or height.
+    ResizePixels {
+        width: u16,
+        height: u16,
+    },
+    /// Resizes the text area to a size in cells. A zero keeps the current
+    /// number of columns or rows.
+    ResizeCells {
+        cols: u16,
+        rows: u16,
+    },
+    Raise,
+    Maximize(bool),
+    /// Enters or leaves fullscreen, or toggles it when `None`
+    Fullscreen(Option<bool>),
+    Progress(Progress),
+}
+
+/// Progress of a task reported with OSC 9;4, in percent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Progress {
+    #[default]
+    None,
+    Normal(u8),
+    Error(u8),
+    Indeterminate,
+    Paused(u8),
+}
+
+impl Progress {
+    pub fn percent(self) -> Option<u8> {
+        match self {
+            Progress::Normal(percent) | Progress::Error(percent) | Progress::Paused(percent) => {
+                Some(percent)
+            }
+            Progress::None | Progress::Indeterminate => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Terminal {
     terminal: Arc<Mutex<TerminalInner>>,
+    pty: PtySession,
+    events: Receiver<TerminalEvent>,
 }
 
 impl Terminal {
-    pub fn new(pty: PtySession) -> Self {
+    pub fn new(
+        pty: PtySession,
+        config: Config,
+        clipboard: SharedClipboard,
+        notifier: SharedNotifier,
+    ) -> Self {
         let reader = pty.get_reader();
-        let inner = Arc::new(Mutex::new(TerminalInner::new(pty)));
-        let terminal = Terminal { terminal: inner };
+        let (sender, events) = crossbeam_channel::unbounded();
+        let inner = Arc::new(Mutex::new(TerminalInner::new(
+            pty.get_writer(),
+            config,
+            sender,
+            clipboard,
+            notifier,
+        )));
+        let terminal = Terminal {
+            terminal: inner,
+            pty,
+            events,
+        };
         terminal.start_feeding(reader);
         terminal
     }
 
-    pub fn as_text(&self) -> String {
-        self.terminal
-            .lock()
-            .expect("Failed to lock terminal")
-            .as_text()
+    pub fn events(&self) -> &Receiver<TerminalEvent> {
+        &self.events
     }
 
+    /// Sends input to the program, scrolling the view back to the screen
     pub fn write(&self, data: &[u8]) {
+        let mut terminal = self.terminal.lock().expect("Failed to lock terminal");
+        terminal.set_display_offset(0);
+        terminal.write(data);
+    }
+
+    /// Tells the terminal whether the window has the focus, reporting it to
+    /// the program when it asked for focus events
+    pub fn set_focused(&self, focused: bool) {
         self.terminal
             .lock()
             .expect("Failed to lock terminal")
-            .write(data);
+            .set_focused(focused);
     }
 
-    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+    /// Resizes the grid, `cell` being the size of a cell in pixels as laid
+    /// out by the renderer
+    pub fn resize(&self, cols: u16, rows: u16, cell: CellSize) -> Result<()> {
         let mut terminal = self.terminal.lock().expect("Failed to lock terminal");
         tracing::info!("Resizing terminal to {} cols and {} rows", cols, rows);
-        terminal.size = Some(Size { cols, rows });
-        terminal.pty.resize(cols, rows)
+        terminal.resize(Size { cols, rows });
+        terminal.cell_size = cell;
+        self.pty.resize(
+            cols,
+            rows,
+            cols.saturating_mul(cell.width),
+            rows.saturating_mul(cell.height),
+        )
+    }
+
+    /// Returns a copy of the rows on screen
+    pub fn visible_rows(&self) -> Vec<Row> {
+        let terminal = self.terminal.lock().expect("Failed to lock terminal");
+        (0..terminal.rows())
+            .filter_map(|y| terminal.grid.visible_row(y))
+            .cloned()
+            .collect()
+    }
+
+    /// Position of the cursor in the view, unless it is hidden (DECTCEM) or
+    /// scrolled out of view
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        let terminal = self.terminal.lock().expect("Failed to lock terminal");
+        if !terminal.modes.get(Mode::ShowCursor) {
+            return None;
+        }
+        let y = terminal.cursor.y + terminal.grid.display_offset();
+        (y < terminal.rows()).then_some((terminal.cursor.x, y))
+    }
+
+    pub fn size(&self) -> Size {
+        self.terminal.lock().expect("Failed to lock terminal").size
+    }
+
+    /// Scrolls the view by `delta` lines, back into the scrollback when
+    /// positive
+    pub fn scroll_display(&self, delta: isize) {
+        let mut terminal = self.terminal.lock().expect("Failed to lock terminal");
+        let offset = terminal.grid.display_offset().saturating_add_signed(delta);
+        terminal.set_display_offset(offset);
+    }
+
+    /// Scrolls the view so the previous or the next prompt is at the top
+    pub fn scroll_to_prompt(&self, previous: bool) {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .scroll_to_prompt(previous);
+    }
+
+    /// Returns the output of the last command, as marked by OSC 133
+    pub fn last_command_output(&self) -> Option<String> {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .last_command_output()
+    }
+
+    /// Whether the alternate screen is shown, which has no scrollback
+    pub fn alt_screen(&self) -> bool {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .alt_screen
+    }
+
+    /// Whether a mode set by SM or DECSET is enabled
+    pub fn mode(&self, mode: Mode) -> bool {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .modes
+            .get(mode)
+    }
+
+    /// How the program wants mouse events reported, if at all
+    pub fn mouse_protocol(&self) -> Option<MouseProtocol> {
+        let terminal = self.terminal.lock().expect("Failed to lock terminal");
+        let modes = &terminal.modes;
+        let tracking = if modes.get(Mode::AnyEventMouse) {
+            Tracking::Motion
+        } else if modes.get(Mode::ButtonEventMouse) {
+            Tracking::Drag
+        } else if modes.get(Mode::NormalMouse) {
+            Tracking::Click
+        } else if modes.get(Mode::X10Mouse) {
+            Tracking::Press
+        } else {
+            return None;
+        };
+        let encoding = if modes.get(Mode::SgrPixelsMouse) {
+            Encoding::SgrPixels
+        } else if modes.get(Mode::SgrMouse) {
+            Encoding::Sgr
+        } else if modes.get(Mode::UrxvtMouse) {
+            Encoding::Urxvt
+        } else if modes.get(Mode::Utf8Mouse) {
+            Encoding::Utf8
+        } else {
+            Encoding::Default
+        };
+        Some(MouseProtocol { tracking, encoding })
+    }
+
+    /// Flags of the kitty keyboard protocol set on the active screen
+    pub fn keyboard_flags(&self) -> KeyboardFlags {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .keyboard_flags
+            .current()
+    }
+
+    /// Level of xterm's modifyOtherKeys set by the program
+    pub fn modify_other_keys(&self) -> u8 {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .modify_other_keys
+    }
+
+    /// Working directory of the shell, as reported with OSC 7
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .current_dir
+            .clone()
+    }
+
+    /// Progress of the running task, as reported with OSC 9;4
+    pub fn progress(&self) -> Progress {
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .progress
     }
 
-    pub fn cursor(&self) -> (usize, usize) {
+    /// Returns the link of a cell on screen, if any
+    pub fn hyperlink_at(&self, x: usize, y: usize) -> Option<Arc<Hyperlink>> {
         let terminal = self.terminal.lock().expect("Failed to lock terminal");
-        (terminal.cursor_x, terminal.cursor_y)
+        let cell = terminal.grid.visible_row(y)?.cells().get(x)?;
+        cell.attrs.hyperlink.clone()
     }
 
     pub fn is_dirty(&self) -> bool {
-        self.terminal.lock().expect("Failed to lock terminal").is_dirty()
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .is_dirty()
     }
 
     pub fn clear_dirty(&self) {
-        self.terminal.lock().expect("Failed to lock terminal").clear_dirty();
+        self.terminal
+            .lock()
+            .expect("Failed to lock terminal")
+            .clear_dirty();
     }
 
     fn start_feeding(&self, reader: Receiver<String>) {
@@ -74,32 +321,164 @@ impl Terminal {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Size {
     pub cols: u16,
     pub rows: u16,
 }
 
+impl Default for Size {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+/// Size of a cell in physical pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Cursor {
+    x: usize,
+    y: usize,
+    /// Set after printing in the last column. The wrap to the next line is
+    /// deferred until the next character is printed, like xterm does.
+    pending_wrap: bool,
+    /// Attributes given to printed characters, set by SGR
+    attrs: Attributes,
+    charsets: Charsets,
+}
+
+/// State saved by DECSC and restored by DECRC
+#[derive(Clone, Debug, Default)]
+struct SavedCursor {
+    cursor: Cursor,
+    origin_mode: bool,
+}
+
+/// Titles set with OSC 0, 1 and 2
+#[derive(Clone, Debug, Default)]
+struct Titles {
+    window: String,
+    icon: String,
+}
+
+/// Titles saved by XTPUSHTITLE, which may save only one of them
+#[derive(Clone, Debug, Default)]
+struct PushedTitles {
+    window: Option<String>,
+    icon: Option<String>,
+}
+
 struct TerminalInner {
-    pub lines: VecDeque<String>,
-    pub cursor_x: usize,
-    pub cursor_y: usize,
-    pty: PtySession,
+    grid: Grid,
+    /// The screen that isn't shown, primary or alternate
+    inactive_grid: Grid,
+    /// Whether the alternate screen is the one being shown
+    alt_screen: bool,
+    cursor: Cursor,
+    /// Row of the cursor on the inactive screen when it was left, which that
+    /// screen is resized around
+    inactive_cursor_y: usize,
+    /// Cursor saved on the active screen
+    saved_cursor: Option<SavedCursor>,
+    /// Cursor saved on the inactive screen
+    inactive_saved_cursor: Option<SavedCursor>,
+    /// Modes set by SM/RM and DECSET/DECRST
+    modes: Modes,
+    /// Kitty keyboard protocol flags of the active screen
+    keyboard_flags: KeyboardFlagsStack,
+    /// Kitty keyboard protocol flags of the inactive screen
+    inactive_keyboard_flags: KeyboardFlagsStack,
+    /// xterm modifyOtherKeys level, from 0 to 2
+    modify_other_keys: u8,
+    /// Columns with a tab stop
+    tab_stops: Vec<bool>,
+    /// Last printed character, repeated by REP
+    last_char: Option<char>,
+    /// Top and bottom margins set by DECSTBM
+    scroll_region: Range<usize>,
+    /// Left and right margins set by DECSLRM
+    margins: Range<usize>,
+    /// Window and icon titles
+    titles: Titles,
+    /// Titles saved by XTPUSHTITLE, restored by XTPOPTITLE
+    title_stack: Vec<PushedTitles>,
+    /// Working directory reported by the shell with OSC 7
+    current_dir: Option<PathBuf>,
+    /// Progress reported with OSC 9;4
+    progress: Progress,
+    /// Input for the program, written to the PTY
+    writer: Sender<Vec<u8>>,
+    config: Config,
+    events: Sender<TerminalEvent>,
+    /// Clipboard read and written by OSC 52
+    clipboard: SharedClipboard,
+    /// Shows notifications sent with OSC 9, 99 and 777
+    notifier: SharedNotifier,
+    /// OSC 99 notification still being received in chunks, with its id
+    pending_notification: Option<(Option<String>, Notification)>,
+    /// Whether the window has the focus
+    focused: bool,
     parser: Parser,
-    size: Option<Size>,
+    size: Size,
+    cell_size: CellSize,
     dirty: bool,
 }
 
 impl TerminalInner {
-    const MAX_LINES: usize = 1000;
+    const MAX_SCROLLBACK: usize = 1000;
+    const TAB_WIDTH: usize = 8;
+    const MAX_TITLE_STACK: usize = 10;
+    /// Longer titles are truncated, in characters
+    const MAX_TITLE_LENGTH: usize = 256;
+    /// Links with a longer URI are ignored, in bytes
+    const MAX_URI_LENGTH: usize = 4096;
+    /// Longer notification bodies are truncated, in characters
+    const MAX_NOTIFICATION_LENGTH: usize = 1024;
 
-    pub fn new(pty: PtySession) -> Self {
+    pub fn new(
+        writer: Sender<Vec<u8>>,
+        config: Config,
+        events: Sender<TerminalEvent>,
+        clipboard: SharedClipboard,
+        notifier: SharedNotifier,
+    ) -> Self {
+        let size = Size::default();
         Self {
-            lines: VecDeque::with_capacity(Self::MAX_LINES),
-            cursor_x: 0,
-            cursor_y: 0,
-            pty,
+            grid: Grid::new(size, Self::MAX_SCROLLBACK),
+            // The alternate screen never has any scrollback
+            inactive_grid: Grid::new(size, 0),
+            alt_screen: false,
+            cursor: Cursor::default(),
+            inactive_cursor_y: 0,
+            saved_cursor: None,
+            inactive_saved_cursor: None,
+            modes: Modes::default(),
+            keyboard_flags: KeyboardFlagsStack::default(),
+            inactive_keyboard_flags: KeyboardFlagsStack::default(),
+            modify_other_keys: 0,
+            tab_stops: default_tab_stops(size.cols as usize),
+            last_char: None,
+            scroll_region: 0..size.rows as usize,
+            margins: 0..size.cols as usize,
+            titles: Titles::default(),
+            title_stack: Vec::new(),
+            current_dir: None,
+            progress: Progress::None,
+            writer,
+            config,
+            events,
+            clipboard,
+            notifier,
+            pending_notification: None,
+            focused: false,
             parser: Parser::new(),
-            size: None,
+            size,
+            cell_size: CellSize::default(),
             dirty: false,
         }
     }
@@ -110,20 +489,6 @@ impl TerminalInner {
         self.parser = parser;
     }
 
-    pub fn as_text(&self) -> String {
-        return self
-            .lines
-            .iter()
-            .skip(
-                self.lines
-                    .len()
-                    .saturating_sub(self.size.as_ref().map_or(0, |s| s.rows as usize)),
-            )
-            .cloned()
-            .collect::<Vec<_>>()
-            .join("\n");
-    }
-
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
@@ -132,254 +497,2358 @@ impl TerminalInner {
         self.dirty = false;
     }
 
+    fn resize(&mut self, size: Size) {
+        let size = Size {
+            cols: size.cols.max(1),
+            rows: size.rows.max(1),
+        };
+        if size == self.size {
+            return;
+        }
+        // Each screen is resized around its own cursor, and the cursor saved
+        // on it follows its lines
+        let rows = size.rows as usize;
+        let shift = self.grid.resize(size, self.cursor.y);
+        self.cursor.y = shift_row(self.cursor.y, shift, rows);
+        if let Some(saved) = &mut self.saved_cursor {
+            saved.cursor.y = shift_row(saved.cursor.y, shift, rows);
+        }
+        let shift = self.inactive_grid.resize(size, self.inactive_cursor_y);
+        self.inactive_cursor_y = shift_row(self.inactive_cursor_y, shift, rows);
+        if let Some(saved) = &mut self.inactive_saved_cursor {
+            saved.cursor.y = shift_row(saved.cursor.y, shift, rows);
+        }
+        self.cursor.x = self.cursor.x.min(size.cols as usize - 1);
+        self.cursor.pending_wrap = false;
+        self.scroll_region = 0..size.rows as usize;
+        self.margins = 0..size.cols as usize;
+        if size.cols != self.size.cols {
+            self.tab_stops = default_tab_stops(size.cols as usize);
+        }
+        self.size = size;
+        self.dirty = true;
+    }
+
     fn write(&mut self, data: &[u8]) {
         if data.is_empty() {
             return; // Skip empty writes
         }
-        let command = match data {
-            b"\x08" // Replace backspace key with DEL
-             => b"\x7f", // DEL
-            _ => data
-        };
-        self.pty
-            .get_writer()
-            .send(command.to_vec())
+        self.writer
+            .send(data.to_vec())
             .expect("Failed to write to PTY");
     }
 
+    fn cols(&self) -> usize {
+        self.size.cols as usize
+    }
+
+    fn rows(&self) -> usize {
+        self.size.rows as usize
+    }
+
     fn move_cursor(&mut self, x: usize, y: usize) {
         tracing::debug!(
             "Moving cursor from ({}, {}) to ({}, {})",
-            self.cursor_x,
-            self.cursor_y,
+            self.cursor.x,
+            self.cursor.y,
             x,
             y
         );
-        self.cursor_x = x;
-        self.cursor_y = y;
+        // Keep the cursor within the screen
+        self.cursor.x = x.min(self.cols() - 1);
+        self.cursor.y = y.min(self.rows() - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Moves the cursor to a position addressed by CUP, which is relative to
+    /// the margins in origin mode
+    fn goto(&mut self, x: usize, y: usize) {
+        self.goto_row(y);
+        self.goto_column(x);
+    }
+
+    fn goto_column(&mut self, x: usize) {
+        let x = if self.modes.get(Mode::Origin) {
+            let margins = self.horizontal_margins();
+            (margins.start + x).min(margins.end - 1)
+        } else {
+            x
+        };
+        self.move_cursor(x, self.cursor.y);
+    }
+
+    fn goto_row(&mut self, y: usize) {
+        let y = if self.modes.get(Mode::Origin) {
+            (self.scroll_region.start + y).min(self.scroll_region.end - 1)
+        } else {
+            y
+        };
+        self.move_cursor(self.cursor.x, y);
+    }
+
+    /// Moves the cursor vertically without leaving the scroll region, unless
+    /// it already was outside of it
+    fn move_cursor_vertically(&mut self, delta: isize) {
+        let y = self.cursor.y;
+        let region = &self.scroll_region;
+        let (top, bottom) = match y {
+            y if y < region.start => (0, region.end - 1),
+            y if y >= region.end => (region.start, self.rows() - 1),
+            _ => (region.start, region.end - 1),
+        };
+        let y = y.saturating_add_signed(delta).clamp(top, bottom);
+        self.move_cursor(self.cursor.x, y);
+    }
+
+    /// Moves the cursor horizontally without leaving the margins, unless it
+    /// already was outside of them
+    fn move_cursor_horizontally(&mut self, delta: isize) {
+        let x = self.cursor.x;
+        let margins = self.horizontal_margins();
+        let (left, right) = match x {
+            x if x < margins.start => (0, margins.end - 1),
+            x if x >= margins.end => (margins.start, self.cols() - 1),
+            _ => (margins.start, margins.end - 1),
+        };
+        let x = x.saturating_add_signed(delta).clamp(left, right);
+        self.move_cursor(x, self.cursor.y);
+    }
 
-        // Ensure cursor position is within bounds
-        if self.cursor_y >= self.lines.len() {
-            self.cursor_y = self.lines.len().saturating_sub(1);
+    /// Left and right margins currently in effect
+    fn horizontal_margins(&self) -> Range<usize> {
+        if self.modes.get(Mode::LeftRightMargin) {
+            self.margins.clone()
+        } else {
+            0..self.cols()
         }
-        if self.cursor_x > self.lines[self.cursor_y].chars().count() {
-            self.cursor_x = self.lines[self.cursor_y].chars().count();
+    }
+
+    /// Cell used to fill erased areas. Only the background color is kept from
+    /// the current attributes.
+    fn blank_cell(&self) -> Cell {
+        Cell::blank(Attributes {
+            bg: self.cursor.attrs.bg,
+            ..Default::default()
+        })
+    }
+
+    /// Scrolls the scroll region up, within the left and right margins
+    fn scroll_up(&mut self, count: usize) {
+        let margins = self.horizontal_margins();
+        let template = self.blank_cell();
+        self.grid
+            .scroll_up(self.scroll_region.clone(), margins, count, &template);
+    }
+
+    /// Scrolls the scroll region down, within the left and right margins
+    fn scroll_down(&mut self, count: usize) {
+        let margins = self.horizontal_margins();
+        let template = self.blank_cell();
+        self.grid
+            .scroll_down(self.scroll_region.clone(), margins, count, &template);
+    }
+
+    fn carriage_return(&mut self) {
+        let left = self.horizontal_margins().start;
+        self.cursor.x = if self.cursor.x >= left { left } else { 0 };
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Moves the cursor down one line, scrolling the region at the bottom
+    /// margin (IND)
+    fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.y + 1 == self.scroll_region.end {
+            if self.horizontal_margins().contains(&self.cursor.x) {
+                self.scroll_up(1);
+            }
+        } else if self.cursor.y + 1 < self.rows() {
+            self.cursor.y += 1;
         }
-        tracing::debug!("Cursor moved to ({}, {})", self.cursor_x, self.cursor_y);
     }
-}
 
-impl Perform for TerminalInner {
-    fn print(&mut self, c: char) {
-        // If the cursor position exceeds the current line, extend the lines vector
-        if self.cursor_y >= self.lines.len() {
-            self.lines.resize(self.cursor_y + 1, String::new());
+    /// Moves the cursor up one line, scrolling the region down at the top
+    /// margin (RI)
+    fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.y == self.scroll_region.start {
+            if self.horizontal_margins().contains(&self.cursor.x) {
+                self.scroll_down(1);
+            }
+        } else if self.cursor.y > 0 {
+            self.cursor.y -= 1;
         }
+    }
+
+    /// NEL
+    fn next_line(&mut self) {
+        self.linefeed();
+        self.carriage_return();
+    }
 
-        let line = &mut self.lines[self.cursor_y];
+    /// DECSTBM
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows());
+        if top >= bottom {
+            tracing::debug!("Invalid scroll region: {}..{}", top, bottom);
+            return;
+        }
+        self.scroll_region = top - 1..bottom;
+        self.goto(0, 0);
+    }
 
-        // Ensure the line is long enough to accommodate the cursor position
-        let char_count = line.chars().count();
-        if self.cursor_x > char_count {
-            line.extend(std::iter::repeat(' ').take(self.cursor_x - char_count));
+    /// DECSLRM
+    fn set_margins(&mut self, left: usize, right: usize) {
+        let right = right.min(self.cols());
+        if left >= right {
+            tracing::debug!("Invalid margins: {}..{}", left, right);
+            return;
         }
+        self.margins = left - 1..right;
+        self.goto(0, 0);
+    }
 
-        let updated_char_count = line.chars().count();
-        if self.cursor_x == updated_char_count {
-            line.push(c);
-        } else {
-            if let Some((start, _)) = line.char_indices().nth(self.cursor_x) {
-                let end = line
-                    .char_indices()
-                    .nth(self.cursor_x + 1)
-                    .map(|(i, _)| i)
-                    .unwrap_or(line.len());
-
-                line.replace_range(start..end, &c.to_string());
-            } else {
-                // if cursor_x is out of bounds, just append
-                line.push(c);
+    fn erase_in_display(&mut self, mode: u16) {
+        let template = self.blank_cell();
+        let Cursor { x, y, .. } = self.cursor;
+        self.cursor.pending_wrap = false;
+        let cols = self.cols();
+        match mode {
+            // Erase from cursor to end of screen
+            0 => {
+                self.grid.row_mut(y).erase(x..cols, &template);
+                for y in y + 1..self.rows() {
+                    self.grid.row_mut(y).reset(&template);
+                }
+            }
+            // Erase from start of screen to cursor
+            1 => {
+                for y in 0..y {
+                    self.grid.row_mut(y).reset(&template);
+                }
+                self.grid.row_mut(y).erase(0..x + 1, &template);
             }
+            // Erase entire screen
+            2 => self.clear_screen(),
+            // Erase scrollback
+            3 => self.grid.clear_scrollback(),
+            _ => tracing::debug!("Unknown erase in display mode: {}", mode),
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let template = self.blank_cell();
+        let Cursor { x, y, .. } = self.cursor;
+        self.cursor.pending_wrap = false;
+        let range = match mode {
+            // Erase from cursor to end of line
+            0 => x..self.cols(),
+            // Erase from start of line to cursor
+            1 => 0..x + 1,
+            // Erase entire line
+            2 => 0..self.cols(),
+            _ => {
+                tracing::debug!("Unknown erase in line mode: {}", mode);
+                return;
+            }
+        };
+        self.grid.row_mut(y).erase(range, &template);
+    }
+
+    /// Writes a character at the cursor and advances it, wrapping at the
+    /// right margin
+    fn put_char(&mut self, c: char) {
+        let Some(width) = c.width().filter(|&width| width > 0) else {
+            // Combining characters are not supported yet
+            tracing::debug!("Dropping zero-width character {:?}", c);
+            return;
+        };
+        // The line ends at the right margin, unless the cursor is already
+        // past it
+        let margins = self.horizontal_margins();
+        let cols = if self.cursor.x < margins.end {
+            margins.end
+        } else {
+            self.cols()
+        };
+        let width = width.min(cols);
+
+        // A wide character that doesn't fit in the last column is moved to
+        // the next line as a whole
+        if self.modes.get(Mode::AutoWrap)
+            && (self.cursor.pending_wrap || self.cursor.x + width > cols)
+        {
+            self.wrap_line();
+        }
+        let x = self.cursor.x.min(cols - width);
+
+        let attrs = self.cursor.attrs.clone();
+        let template = self.blank_cell();
+        let insert = self.modes.get(Mode::Insert);
+        let row = self.grid.row_mut(self.cursor.y);
+        // In insert mode, the rest of the line is shifted right first
+        if insert {
+            row.insert_cells(x, width, cols, &template);
+        }
+        row.clear_wide(x);
+        row[x] = Cell {
+            c,
+            attrs: attrs.clone(),
+            width: width as u8,
+        };
+        if width > 1 {
+            row.clear_wide(x + 1);
+            row[x + 1] = Cell {
+                c: ' ',
+                attrs,
+                width: 0,
+            };
         }
 
-        self.cursor_x += 1;
+        if x + width == cols {
+            self.cursor.x = cols - 1;
+            self.cursor.pending_wrap = self.modes.get(Mode::AutoWrap);
+        } else {
+            self.cursor.x = x + width;
+        }
         self.dirty = true;
     }
 
-    fn execute(&mut self, byte: u8) {
-        // eprintln!("Execute byte: {:?}", byte);
-        match byte {
-            b'\n' => {
-                self.cursor_x = 0;
-                self.cursor_y += 1;
-                if self.cursor_y >= Self::MAX_LINES {
-                    self.lines.pop_front();
-                    self.cursor_y = Self::MAX_LINES - 1;
+    /// ICH
+    fn insert_chars(&mut self, count: usize) {
+        let margins = self.horizontal_margins();
+        if !margins.contains(&self.cursor.x) {
+            return;
+        }
+        let template = self.blank_cell();
+        let Cursor { x, y, .. } = self.cursor;
+        self.grid
+            .row_mut(y)
+            .insert_cells(x, count, margins.end, &template);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// DCH
+    fn delete_chars(&mut self, count: usize) {
+        let margins = self.horizontal_margins();
+        if !margins.contains(&self.cursor.x) {
+            return;
+        }
+        let template = self.blank_cell();
+        let Cursor { x, y, .. } = self.cursor;
+        self.grid
+            .row_mut(y)
+            .delete_cells(x, count, margins.end, &template);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// ECH
+    fn erase_chars(&mut self, count: usize) {
+        let template = self.blank_cell();
+        let Cursor { x, y, .. } = self.cursor;
+        self.grid
+            .row_mut(y)
+            .erase(x..x.saturating_add(count), &template);
+        self.cursor.pending_wrap = false;
+    }
+
+    /// IL, DL: the lines from the cursor to the bottom margin are scrolled
+    /// down or up, as long as the cursor is inside the margins
+    fn insert_or_delete_lines(&mut self, count: usize, insert: bool) {
+        let margins = self.horizontal_margins();
+        if !self.scroll_region.contains(&self.cursor.y) || !margins.contains(&self.cursor.x) {
+            return;
+        }
+        let template = self.blank_cell();
+        let rows = self.cursor.y..self.scroll_region.end;
+        if insert {
+            self.grid.scroll_down(rows, margins, count, &template);
+        } else {
+            self.grid.scroll_up(rows, margins, count, &template);
+        }
+        self.carriage_return();
+    }
+
+    /// REP, repeats the last printed character
+    fn repeat_char(&mut self, count: usize) {
+        let Some(c) = self.last_char else {
+            return;
+        };
+        // Nothing is gained by repeating past a full screen
+        for _ in 0..count.min(self.cols() * self.rows()) {
+            self.put_char(c);
+        }
+    }
+
+    /// HT, CHT: moves to the next tab stop `count` times, stopping at the
+    /// right margin when the cursor starts inside the margins, or at the
+    /// last column
+    fn forward_tab(&mut self, count: usize) {
+        let margins = self.horizontal_margins();
+        let end = if margins.contains(&self.cursor.x) {
+            margins.end
+        } else {
+            self.cols()
+        };
+        let mut x = self.cursor.x;
+        for _ in 0..count {
+            match (x + 1..end).find(|&x| self.tab_stops[x]) {
+                Some(stop) => x = stop,
+                None => {
+                    x = end - 1;
+                    break;
                 }
             }
-            b'\r' => {
-                self.cursor_x = 0;
-            }
-            b'\x08' => {
-                // Backspace
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
+        }
+        self.move_cursor(x, self.cursor.y);
+    }
+
+    /// CBT, moves to the previous tab stop `count` times, stopping at the
+    /// first column
+    fn backward_tab(&mut self, count: usize) {
+        let mut x = self.cursor.x;
+        for _ in 0..count {
+            match (0..x).rev().find(|&x| self.tab_stops[x]) {
+                Some(stop) => x = stop,
+                None => {
+                    x = 0;
+                    break;
                 }
             }
-            _ => {}
         }
+        self.move_cursor(x, self.cursor.y);
+    }
+
+    /// TBC
+    fn clear_tab_stops(&mut self, mode: u16) {
+        match mode {
+            // Clear the stop at the cursor
+            0 => self.tab_stops[self.cursor.x] = false,
+            // Clear all stops
+            3 => self.tab_stops.fill(false),
+            _ => tracing::debug!("Unknown tab clear mode: {}", mode),
+        }
+    }
+
+    /// Continues on the next line after the pending wrap, marking the current
+    /// line as soft-wrapped
+    fn wrap_line(&mut self) {
+        self.grid.row_mut(self.cursor.y).set_wrapped(true);
+        self.next_line();
+    }
+
+    /// DECSC
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            cursor: self.cursor.clone(),
+            origin_mode: self.modes.get(Mode::Origin),
+        });
+    }
+
+    /// DECRC, which resets the cursor to the home position when nothing was
+    /// saved
+    fn restore_cursor(&mut self) {
+        let SavedCursor {
+            cursor,
+            origin_mode,
+        } = self.saved_cursor.clone().unwrap_or_default();
+        self.modes.set(Mode::Origin, origin_mode);
+        self.cursor = Cursor {
+            x: cursor.x.min(self.cols() - 1),
+            y: cursor.y.min(self.rows() - 1),
+            ..cursor
+        };
+    }
+
+    /// RIS, resets the screens, the cursor and the modes. The size, the
+    /// titles and the state of the shell and the window are kept.
+    fn reset(&mut self) {
+        tracing::debug!("Resetting terminal");
+        self.grid = Grid::new(self.size, Self::MAX_SCROLLBACK);
+        self.inactive_grid = Grid::new(self.size, 0);
+        self.alt_screen = false;
+        self.cursor = Cursor::default();
+        self.inactive_cursor_y = 0;
+        self.saved_cursor = None;
+        self.inactive_saved_cursor = None;
+        self.modes = Modes::default();
+        self.keyboard_flags = KeyboardFlagsStack::default();
+        self.inactive_keyboard_flags = KeyboardFlagsStack::default();
+        self.modify_other_keys = 0;
+        self.tab_stops = default_tab_stops(self.cols());
+        self.last_char = None;
+        self.scroll_region = 0..self.rows();
+        self.margins = 0..self.cols();
+        self.title_stack.clear();
+        self.pending_notification = None;
+        self.set_progress(Progress::None);
         self.dirty = true;
     }
 
-    fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {
-        tracing::debug!(
-            "OSC Dispatch: params={:?}, bell_terminated={}",
-            _params,
-            _bell_terminated
-        );
+    /// Switches between the primary and the alternate screen
+    fn set_alt_screen(&mut self, enabled: bool) {
+        if self.alt_screen == enabled {
+            return;
+        }
+        std::mem::swap(&mut self.grid, &mut self.inactive_grid);
+        // The cursor stays where it is, but the screen being left is resized
+        // around the row it was on
+        self.inactive_cursor_y = self.cursor.y;
+        self.grid.set_display_offset(0);
+        self.inactive_grid.set_display_offset(0);
+        std::mem::swap(&mut self.saved_cursor, &mut self.inactive_saved_cursor);
+        std::mem::swap(&mut self.keyboard_flags, &mut self.inactive_keyboard_flags);
+        self.alt_screen = enabled;
+        self.cursor.pending_wrap = false;
     }
 
-    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
-        // eprintln!(
-        //     "CSI Dispatch: params={:?}, intermediates={:?}, ignore={}, c='{}'",
-        //     params, intermediates, ignore, c
-        // );
-        let params: Vec<&[u16]> = params.iter().collect();
-        // Handle some common CSI sequences
-        match c {
-            'H' | 'f' => {
-                // Cursor Position
-                let row = params.get(0).and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-                let col = params.get(1).and_then(|p| p.first()).copied().unwrap_or(1) as usize;
-
-                self.cursor_y = row.saturating_sub(1);
-                self.cursor_x = col.saturating_sub(1);
-            }
-            'J' => {
-                // Erase in Display
-                let param = params.get(0).and_then(|p| p.first()).copied().unwrap_or(0);
-                // Erase from cursor to end of screen
-                if param == 0 {
-                    tracing::debug!("Erasing from cursor to end of screen");
-                    for line in self.lines.iter_mut().skip(self.cursor_y) {
-                        line.clear();
-                    }
-                    self.lines.truncate(self.cursor_y + 1);
-                    if let Some(line) = self.lines.get_mut(self.cursor_y) {
-                        *line = line.chars().take(self.cursor_x).collect();
-                    }
+    /// Blanks the whole screen without moving the cursor
+    fn clear_screen(&mut self) {
+        let template = self.blank_cell();
+        for y in 0..self.rows() {
+            self.grid.row_mut(y).reset(&template);
+        }
+    }
+
+    /// SGR
+    fn set_graphics_rendition(&mut self, params: &[&[u16]]) {
+        // Resetting the rendition doesn't end a hyperlink
+        let reset = |attrs: &mut Attributes| {
+            *attrs = Attributes {
+                hyperlink: attrs.hyperlink.take(),
+                ..Default::default()
+            }
+        };
+        if params.is_empty() {
+            reset(&mut self.cursor.attrs);
+            return;
+        }
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            let attrs = &mut self.cursor.attrs;
+            match param {
+                [0] => reset(attrs),
+                [1] => attrs.flags.insert(Flags::BOLD),
+                [2] => attrs.flags.insert(Flags::DIM),
+                [3] => attrs.flags.insert(Flags::ITALIC),
+                [4, 0] => attrs
+                    .flags
+                    .remove(Flags::UNDERLINE | Flags::DOUBLE_UNDERLINE),
+                [4, 2] | [21] => {
+                    attrs.flags.remove(Flags::UNDERLINE);
+                    attrs.flags.insert(Flags::DOUBLE_UNDERLINE);
                 }
-                // Erase from start of screen to cursor
-                else if param == 1 {
-                    tracing::debug!("Erasing from start of screen to cursor");
-                    for line in self.lines.iter_mut().take(self.cursor_y) {
-                        line.clear();
+                // Curly, dotted and dashed underlines are drawn as single ones
+                [4, ..] => {
+                    attrs.flags.remove(Flags::DOUBLE_UNDERLINE);
+                    attrs.flags.insert(Flags::UNDERLINE);
+                }
+                [7] => attrs.flags.insert(Flags::INVERSE),
+                [8] => attrs.flags.insert(Flags::HIDDEN),
+                [9] => attrs.flags.insert(Flags::STRIKEOUT),
+                [22] => attrs.flags.remove(Flags::BOLD | Flags::DIM),
+                [23] => attrs.flags.remove(Flags::ITALIC),
+                [24] => attrs
+                    .flags
+                    .remove(Flags::UNDERLINE | Flags::DOUBLE_UNDERLINE),
+                [27] => attrs.flags.remove(Flags::INVERSE),
+                [28] => attrs.flags.remove(Flags::HIDDEN),
+                [29] => attrs.flags.remove(Flags::STRIKEOUT),
+                [n @ 30..=37] => attrs.fg = Color::Indexed((n - 30) as u8),
+                [38, subparams @ ..] => {
+                    if let Some(color) = parse_extended_color(subparams, &mut params) {
+                        attrs.fg = color;
                     }
-                    if let Some(line) = self.lines.get_mut(self.cursor_y) {
-                        *line = line.chars().skip(self.cursor_x).collect();
+                }
+                [39] => attrs.fg = Color::Default,
+                [n @ 40..=47] => attrs.bg = Color::Indexed((n - 40) as u8),
+                [48, subparams @ ..] => {
+                    if let Some(color) = parse_extended_color(subparams, &mut params) {
+                        attrs.bg = color;
                     }
                 }
-                // Erase entire screen
-                else if param == 2 || param == 3 {
-                    tracing::debug!("Erasing entire screen");
-                    self.lines.clear();
-                    self.cursor_x = 0;
-                    self.cursor_y = 0;
+                [49] => attrs.bg = Color::Default,
+                // Underline colors are parsed so their parameters are skipped,
+                // but aren't supported yet
+                [58, subparams @ ..] => {
+                    parse_extended_color(subparams, &mut params);
                 }
+                [n @ 90..=97] => attrs.fg = Color::Indexed((n - 90 + 8) as u8),
+                [n @ 100..=107] => attrs.bg = Color::Indexed((n - 100 + 8) as u8),
+                _ => tracing::debug!("Unhandled SGR parameter: {:?}", param),
             }
-            // Erase in Line
-            'K' => {
-                let param = params.get(0).and_then(|p| p.first()).copied().unwrap_or(0);
-                if param == 0 {
-                    // Erase from cursor to end of line
-                    tracing::debug!(
-                        "Erasing from cursor to end of line. Cursor at ({}, {})",
-                        self.cursor_x,
-                        self.cursor_y
-                    );
-                    tracing::debug!(
-                        "Current line before erase: {:?}",
-                        self.lines.get(self.cursor_y)
-                    );
-                    tracing::debug!(
-                        "Current line length: {}",
-                        self.lines.get(self.cursor_y).map_or(0, |l| l.len())
-                    );
-                    if let Some(line) = self.lines.get_mut(self.cursor_y) {
-                        // take only the first self.cursor_x characters
-                        *line = line.chars().take(self.cursor_x).collect();
-                    }
-                } else if param == 1 {
-                    // Erase from start of line to cursor
-                    tracing::debug!("Erasing from start of line to cursor");
-                    if let Some(line) = self.lines.get_mut(self.cursor_y) {
-                        *line = line.chars().skip(self.cursor_x).collect();
-                    }
-                } else if param == 2 {
-                    // Erase entire line
-                    tracing::debug!("Erasing entire line");
-                    if let Some(line) = self.lines.get_mut(self.cursor_y) {
-                        line.clear();
-                    }
+        }
+    }
+
+    /// SM/RM and DECSET/DECRST
+    fn set_mode(&mut self, mode: Mode, enabled: bool) {
+        tracing::debug!("Setting mode {:?} to {}", mode, enabled);
+        self.modes.set(mode, enabled);
+        // Enabling a mouse mode disables the others of its kind
+        for group in [Mode::MOUSE_TRACKING, Mode::MOUSE_ENCODINGS] {
+            if enabled && group.contains(&mode) {
+                for &other in group.iter().filter(|&&other| other != mode) {
+                    self.modes.set(other, false);
                 }
             }
-            // Cursor request
-            'n' => {
-                tracing::debug!("Cursor position request received");
-                // Respond with cursor position
-                if params.is_empty() || params[0].is_empty() {
-                    // Respond with current cursor position
-                    let response = format!("\x1b[{};{}R", self.cursor_y + 1, self.cursor_x + 1);
-                    self.write(response.as_bytes());
-                } else {
-                    // Respond with specific position
-                    let row = params[0].get(0).copied().unwrap_or(1) as usize;
-                    let col = params[0].get(1).copied().unwrap_or(1) as usize;
-                    let response = format!("\x1b[{};{}R", row, col);
-                    self.write(response.as_bytes());
+        }
+        match mode {
+            Mode::AutoWrap if !enabled => self.cursor.pending_wrap = false,
+            Mode::Origin => self.goto(0, 0),
+            Mode::LeftRightMargin if !enabled => self.margins = 0..self.cols(),
+            Mode::AltScreen => self.set_alt_screen(enabled),
+            // The alternate screen is cleared when leaving it
+            Mode::AltScreenClear => {
+                if !enabled && self.alt_screen {
+                    self.clear_screen();
                 }
+                self.set_alt_screen(enabled);
             }
-            // Cursor Up
-            'A' => {
-                let count = max(
-                    1,
-                    params.get(0).and_then(|p| p.first()).copied().unwrap_or(1) as usize,
-                );
-                tracing::debug!("Cursor Up by {}, other params: {:?}", count, params);
-                self.move_cursor(self.cursor_x, self.cursor_y.saturating_sub(count));
+            Mode::SaveCursor => {
+                if enabled {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
             }
-            // Cursor Down
-            'B' => {
-                let count = max(
-                    1,
-                    params.get(0).and_then(|p| p.first()).copied().unwrap_or(1) as usize,
-                );
-                tracing::debug!("Cursor Down by {}, other params: {:?}", count, params);
-                self.move_cursor(self.cursor_x, self.cursor_y.saturating_add(count));
+            // The cursor is saved on the primary screen, and the alternate
+            // screen starts out blank
+            Mode::AltScreenSaveCursor => {
+                if enabled {
+                    if !self.alt_screen {
+                        self.save_cursor();
+                        self.set_alt_screen(true);
+                        self.clear_screen();
+                    }
+                } else if self.alt_screen {
+                    self.set_alt_screen(false);
+                    self.restore_cursor();
+                }
             }
-            // Cursor Right
-            'C' => {
-                let count = max(
-                    1,
-                    params.get(0).and_then(|p| p.first()).copied().unwrap_or(1) as usize,
+            _ => {}
+        }
+    }
+
+    /// Sets the modes numbered in `params`, which are DEC private modes when
+    /// `private` is set
+    fn set_modes(&mut self, params: &[&[u16]], private: bool, enabled: bool) {
+        for &number in params.iter().filter_map(|p| p.first()) {
+            match Mode::from_number(number, private) {
+                Some(mode) => self.set_mode(mode, enabled),
+                None => tracing::debug!(
+                    "Unhandled mode {} (private: {}, enabled: {})",
+                    number,
+                    private,
+                    enabled
+                ),
+            }
+        }
+    }
+
+    /// DECRQM, reports whether a mode is set (1), reset (2) or unknown (0)
+    fn report_mode(&mut self, number: u16, private: bool) {
+        let state = match Mode::from_number(number, private) {
+            // Any of the alternate screen modes is set while it is shown
+            Some(Mode::AltScreen | Mode::AltScreenClear | Mode::AltScreenSaveCursor) => {
+                if self.alt_screen { 1 } else { 2 }
+            }
+            Some(mode) if self.modes.get(mode) => 1,
+            Some(_) => 2,
+            None => 0,
+        };
+        let marker = if private { "?" } else { "" };
+        let response = format!("\x1b[{}{};{}$y", marker, number, state);
+        self.write(response.as_bytes());
+    }
+
+    /// DSR, answering the status (5) and cursor position (6) requests. The
+    /// position is relative to the margins in origin mode.
+    fn device_status_report(&mut self, request: u16, private: bool) {
+        let response = match request {
+            5 if !private => "\x1b[0n".to_string(),
+            6 => {
+                let (mut x, mut y) = (self.cursor.x, self.cursor.y);
+                if self.modes.get(Mode::Origin) {
+                    x -= self.horizontal_margins().start.min(x);
+                    y -= self.scroll_region.start.min(y);
+                }
+                // DECXCPR also reports the page, which is always the first
+                if private {
+                    format!("\x1b[?{};{};1R", y + 1, x + 1)
+                } else {
+                    format!("\x1b[{};{}R", y + 1, x + 1)
+                }
+            }
+            _ => {
+                tracing::debug!("Unhandled DSR {} (private: {})", request, private);
+                return;
+            }
+        };
+        self.write(response.as_bytes());
+    }
+
+    fn send_event(&self, event: TerminalEvent) {
+        if self.events.send(event).is_err() {
+            tracing::debug!("Dropping terminal event, the application is gone");
+        }
+    }
+
+    /// Sets the title of the window, notifying the application when it
+    /// changes
+    fn set_window_title(&mut self, title: String) {
+        if title != self.titles.window {
+            self.titles.window = title;
+            self.send_event(TerminalEvent::Title(self.titles.window.clone()));
+        }
+    }
+
+    /// OSC 8, starting a link with `ESC ] 8 ; params ; URI ST` or ending it
+    /// with an empty URI
+    fn set_hyperlink(&mut self, args: &[&[u8]]) {
+        let Some((params, uri)) = args.split_first() else {
+            return;
+        };
+        // The URI may itself contain semicolons
+        let uri = String::from_utf8_lossy(&uri.join(&b';')).into_owned();
+        if uri.is_empty() {
+            self.cursor.attrs.hyperlink = None;
+            return;
+        }
+        if uri.len() > Self::MAX_URI_LENGTH {
+            tracing::debug!("Ignoring hyperlink with a {} bytes long URI", uri.len());
+            return;
+        }
+        // Parameters are `key=value` pairs separated by colons
+        let id = String::from_utf8_lossy(params)
+            .split(':')
+            .find_map(|param| param.strip_prefix("id="))
+            .filter(|id| !id.is_empty())
+            .map(str::to_string);
+        self.cursor.attrs.hyperlink = Some(Arc::new(Hyperlink { id, uri }));
+    }
+
+    fn set_display_offset(&mut self, offset: usize) {
+        if offset != self.grid.display_offset() {
+            self.grid.set_display_offset(offset);
+            self.dirty = true;
+        }
+    }
+
+    fn scroll_to_prompt(&mut self, previous: bool) {
+        let top = self.grid.display_top();
+        let is_prompt = |line: &usize| self.grid.line(*line).marks().contains(Marks::PROMPT);
+        let line = if previous {
+            (0..top).rev().find(is_prompt)
+        } else {
+            (top + 1..self.grid.total_lines()).find(is_prompt)
+        };
+        match line {
+            Some(line) => {
+                let scrollback = self.grid.total_lines() - self.rows();
+                self.set_display_offset(scrollback.saturating_sub(line));
+            }
+            // Past the last prompt, go back to the screen
+            None if !previous => self.set_display_offset(0),
+            None => {}
+        }
+    }
+
+    /// Text between the last OSC 133 C mark and the next D or A mark, with
+    /// wrapped lines joined back together
+    fn last_command_output(&self) -> Option<String> {
+        let grid = &self.grid;
+        let total = grid.total_lines();
+        let start = (0..total)
+            .rev()
+            .find(|&line| grid.line(line).marks().contains(Marks::OUTPUT))?;
+        let start_x = grid.line(start).mark_column(Marks::OUTPUT)?;
+        // The output ends with the command, or where the next prompt starts.
+        // That can be on the line it started on, or in the middle of a line
+        // when the output doesn't end with a newline.
+        let end_x = |line: usize| {
+            let row = grid.line(line);
+            [Marks::COMMAND_END, Marks::PROMPT]
+                .into_iter()
+                .filter_map(|mark| row.mark_column(mark))
+                .filter(|&x| line != start || x >= start_x)
+                .min()
+        };
+        let (end, end_x) = (start..total)
+            .find_map(|line| Some((line, end_x(line)?)))
+            .unwrap_or((total, 0));
+        let mut text = String::new();
+        for line in start..=end.min(total - 1) {
+            let row = grid.line(line);
+            let from = if line == start { start_x } else { 0 };
+            if line == end {
+                text.push_str(row.text_in(from..end_x).trim_end());
+            } else if row.is_wrapped() {
+                text.push_str(&row.text_in(from..row.cells().len()));
+            } else {
+                text.push_str(row.text_in(from..row.cells().len()).trim_end());
+                text.push('\n');
+            }
+        }
+        Some(text.trim_end_matches('\n').to_string())
+    }
+
+    /// OSC 133, marks the prompt, the command and its output on the line of
+    /// the cursor
+    fn set_semantic_mark(&mut self, args: &[&[u8]]) {
+        let Some((kind, options)) = args.split_first() else {
+            return;
+        };
+        let x = self.cursor.x;
+        let row = self.grid.row_mut(self.cursor.y);
+        match *kind {
+            b"A" => row.add_mark(Marks::PROMPT, x),
+            b"B" => row.add_mark(Marks::INPUT, x),
+            b"C" => row.add_mark(Marks::OUTPUT, x),
+            b"D" => {
+                row.add_mark(Marks::COMMAND_END, x);
+                let exit_code = options
+                    .first()
+                    .and_then(|code| std::str::from_utf8(code).ok()?.parse().ok());
+                row.set_exit_code(exit_code);
+            }
+            _ => tracing::debug!("Unhandled OSC 133 mark: {:?}", args),
+        }
+    }
+
+    /// OSC 7, `file://host/path` with the path percent-encoded. A directory
+    /// on another host, as reported through ssh, leaves the current directory
+    /// unknown.
+    fn set_current_dir(&mut self, args: &[&[u8]]) {
+        let uri = args.join(&b';');
+        let Some(rest) = uri.strip_prefix(b"file://") else {
+            tracing::debug!("Ignoring OSC 7 that isn't a file URI");
+            return;
+        };
+        let Some(start) = rest.iter().position(|&b| b == b'/') else {
+            return;
+        };
+        let (host, path) = rest.split_at(start);
+        let host = String::from_utf8_lossy(host);
+        if !is_local_host(&host) {
+            tracing::debug!("Ignoring the current directory on {}", host);
+            self.current_dir = None;
+            return;
+        }
+        let path = percent_decode(path).decode_utf8_lossy();
+        tracing::debug!("Current directory: {}", path);
+        self.current_dir = Some(PathBuf::from(path.into_owned()));
+    }
+
+    fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        if self.modes.get(Mode::FocusEvents) {
+            self.write(if focused { b"\x1b[I" } else { b"\x1b[O" });
+        }
+    }
+
+    /// OSC 9;4, ConEmu's `state;percent` progress report. The percentage may
+    /// be left out for the error and paused states, which then keep the
+    /// current one.
+    fn handle_progress(&mut self, args: &[&[u8]]) {
+        let param = |index: usize| {
+            args.get(index)
+                .and_then(|arg| std::str::from_utf8(arg).ok())
+                .and_then(|arg| arg.parse::<u8>().ok())
+        };
+        let percent = param(1).map(|percent| percent.min(100));
+        let current = self.progress.percent().unwrap_or(0);
+        let progress = match param(0) {
+            Some(0) | None => Progress::None,
+            Some(1) => Progress::Normal(percent.unwrap_or(0)),
+            Some(2) => Progress::Error(percent.unwrap_or(current)),
+            Some(3) => Progress::Indeterminate,
+            Some(4) => Progress::Paused(percent.unwrap_or(current)),
+            Some(state) => {
+                tracing::debug!("Unknown progress state: {}", state);
+                return;
+            }
+        };
+        self.set_progress(progress);
+    }
+
+    fn set_progress(&mut self, progress: Progress) {
+        if progress != self.progress {
+            self.progress = progress;
+            self.send_event(TerminalEvent::Progress(progress));
+            self.dirty = true;
+        }
+    }
+
+    /// OSC 9, iTerm2's notification with only a body. Numbered ConEmu
+    /// commands share the number, and only `9;4` is handled.
+    fn handle_iterm_notification(&mut self, args: &[&[u8]]) {
+        let Some(first) = args.first() else {
+            return;
+        };
+        if !first.is_empty() && first.iter().all(u8::is_ascii_digit) {
+            tracing::debug!("Unhandled ConEmu OSC 9;{}", String::from_utf8_lossy(first));
+            return;
+        }
+        self.notify(Notification {
+            title: String::new(),
+            body: String::from_utf8_lossy(&args.join(&b';')).into_owned(),
+        });
+    }
+
+    /// OSC 777, rxvt-unicode's `notify;title;body`
+    fn handle_urxvt_notification(&mut self, args: &[&[u8]]) {
+        let [b"notify", title, body @ ..] = args else {
+            tracing::debug!("Unhandled OSC 777: {:?}", args);
+            return;
+        };
+        self.notify(Notification {
+            title: String::from_utf8_lossy(title).into_owned(),
+            body: String::from_utf8_lossy(&body.join(&b';')).into_owned(),
+        });
+    }
+
+    /// OSC 99, kitty's notification protocol. The metadata is a list of
+    /// `key=value` separated by colons. A notification may come in several
+    /// chunks with the same id `i`, all but the last one having `d=0`. `p`
+    /// tells whether the payload is the title or the body, and `e=1` that it
+    /// is base64 encoded.
+    fn handle_kitty_notification(&mut self, args: &[&[u8]]) {
+        let [metadata, payload @ ..] = args else {
+            return;
+        };
+        let metadata = String::from_utf8_lossy(metadata);
+        let mut id = None;
+        let mut done = true;
+        let mut kind = "title";
+        let mut encoded = false;
+        for (key, value) in metadata.split(':').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "i" => id = Some(value.to_string()),
+                "d" => done = value != "0",
+                "p" => kind = value,
+                "e" => encoded = value == "1",
+                _ => {}
+            }
+        }
+
+        let payload = payload.join(&b';');
+        let payload = if encoded {
+            match BASE64.decode(&payload) {
+                Ok(payload) => payload,
+                Err(_) => {
+                    tracing::debug!("Ignoring an OSC 99 payload that isn't base64 encoded");
+                    return;
+                }
+            }
+        } else {
+            payload
+        };
+
+        // A chunk with another id starts a new notification
+        if self
+            .pending_notification
+            .as_ref()
+            .is_some_and(|(pending, _)| *pending != id)
+        {
+            self.pending_notification = None;
+        }
+        let (_, notification) = self
+            .pending_notification
+            .get_or_insert_with(|| (id, Notification::default()));
+        let text = match kind {
+            "title" => &mut notification.title,
+            "body" => &mut notification.body,
+            _ => {
+                tracing::debug!("Unhandled OSC 99 payload type: {}", kind);
+                return;
+            }
+        };
+        // Longer texts are truncated anyway
+        if text.len() < Self::MAX_NOTIFICATION_LENGTH * 4 {
+            text.push_str(&String::from_utf8_lossy(&payload));
+        }
+        if done && let Some((_, notification)) = self.pending_notification.take() {
+            self.notify(notification);
+        }
+    }
+
+    /// Shows a notification, unless the window has the focus and the config
+    /// says to drop them then
+    fn notify(&mut self, notification: Notification) {
+        let notification = Notification {
+            title: sanitize_text(notification.title.as_bytes(), Self::MAX_TITLE_LENGTH),
+            body: sanitize_text(notification.body.as_bytes(), Self::MAX_NOTIFICATION_LENGTH),
+        };
+        if notification.title.is_empty() && notification.body.is_empty() {
+            return;
+        }
+        if self.focused && self.config.suppress_focused_notifications {
+            tracing::debug!("Dropping a notification while focused: {:?}", notification);
+            return;
+        }
+        if let Err(e) = self
+            .notifier
+            .lock()
+            .expect("Failed to lock notifier")
+            .notify(&notification)
+        {
+            tracing::warn!("Failed to show a notification: {}", e);
+        }
+    }
+
+    /// OSC 52, sets a selection to base64 encoded text, or replies with its
+    /// contents when the data is `?`. Reading has to be allowed in the
+    /// config.
+    fn handle_clipboard(&mut self, args: &[&[u8]], bell_terminated: bool) {
+        let [selections, data, ..] = args else {
+            return;
+        };
+        // The first supported selection is used. The cut buffers and `s`
+        // are the same as the clipboard.
+        let selection = match selections.iter().find(|c| b"cps01234567".contains(c)) {
+            Some(b'p') => Selection::Primary,
+            _ => Selection::Clipboard,
+        };
+
+        if *data == b"?" {
+            if !self.config.allow_clipboard_read {
+                tracing::info!("Denied a clipboard read, see allow_clipboard_read");
+                return;
+            }
+            let result = self
+                .clipboard
+                .lock()
+                .expect("Failed to lock clipboard")
+                .get(selection);
+            let text = result.unwrap_or_else(|e| {
+                tracing::warn!("Failed to read the clipboard: {}", e);
+                String::new()
+            });
+            let name = match selection {
+                Selection::Clipboard => 'c',
+                Selection::Primary => 'p',
+            };
+            // Reply with the same terminator as the request
+            let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+            let response = format!("\x1b]52;{};{}{}", name, BASE64.encode(text), terminator);
+            self.write(response.as_bytes());
+            return;
+        }
+
+        if !self.config.allow_clipboard_write {
+            tracing::info!("Denied a clipboard write, see allow_clipboard_write");
+            return;
+        }
+        // Check the size before decoding anything
+        if data.len() / 4 * 3 > self.config.clipboard_max_size {
+            tracing::warn!("Ignoring a clipboard write of {} bytes", data.len());
+            return;
+        }
+        let text = match BASE64.decode(data).map(String::from_utf8) {
+            Ok(Ok(text)) => text,
+            _ => {
+                tracing::debug!("Ignoring a clipboard write that isn't base64 encoded text");
+                return;
+            }
+        };
+        let result = self
+            .clipboard
+            .lock()
+            .expect("Failed to lock clipboard")
+            .set(selection, text);
+        if let Err(e) = result {
+            tracing::warn!("Failed to write the clipboard: {}", e);
+        }
+    }
+
+    /// Window manipulation (XTWINOPS). Reports are always answered, but the
+    /// window is only changed when the config allows it.
+    fn window_op(&mut self, params: &[&[u16]]) {
+        let arg = |index| param_or(params, index, 0);
+        let event = match arg(0) {
+            1 => TerminalEvent::Minimize(false),
+            2 => TerminalEvent::Minimize(true),
+            3 => TerminalEvent::Move {
+                x: arg(1) as i32,
+                y: arg(2) as i32,
+            },
+            4 => TerminalEvent::ResizePixels {
+                width: arg(2),
+                height: arg(1),
+            },
+            5 => TerminalEvent::Raise,
+            8 => TerminalEvent::ResizeCells {
+                cols: arg(2),
+                rows: arg(1),
+            },
+            9 if arg(1) <= 1 => TerminalEvent::Maximize(arg(1) == 1),
+            10 => TerminalEvent::Fullscreen(match arg(1) {
+                0 => Some(false),
+                1 => Some(true),
+                _ => None,
+            }),
+            // Text area size in pixels
+            14 => {
+                let response = format!(
+                    "\x1b[4;{};{}t",
+                    self.size.rows.saturating_mul(self.cell_size.height),
+                    self.size.cols.saturating_mul(self.cell_size.width)
+                );
+                self.write(response.as_bytes());
+                return;
+            }
+            // Cell size in pixels
+            16 => {
+                let response =
+                    format!("\x1b[6;{};{}t", self.cell_size.height, self.cell_size.width);
+                self.write(response.as_bytes());
+                return;
+            }
+            // Text area and screen size in cells
+            n @ (18 | 19) => {
+                let response = format!("\x1b[{};{};{}t", n - 10, self.size.rows, self.size.cols);
+                self.write(response.as_bytes());
+                return;
+            }
+            // 1 saves the icon title only, 2 the window title only
+            22 => {
+                if self.title_stack.len() == Self::MAX_TITLE_STACK {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack.push(PushedTitles {
+                    window: (arg(1) != 1).then(|| self.titles.window.clone()),
+                    icon: (arg(1) != 2).then(|| self.titles.icon.clone()),
+                });
+                return;
+            }
+            // Only the titles that were saved are restored, and 1 restores
+            // the icon title only, 2 the window title only
+            23 => {
+                if let Some(titles) = self.title_stack.pop() {
+                    if arg(1) != 2
+                        && let Some(icon) = titles.icon
+                    {
+                        self.titles.icon = icon;
+                    }
+                    if arg(1) != 1
+                        && let Some(window) = titles.window
+                    {
+                        self.set_window_title(window);
+                    }
+                }
+                return;
+            }
+            op => {
+                tracing::debug!("Unhandled window operation {} ({:?})", op, params);
+                return;
+            }
+        };
+        if self.config.allow_window_ops {
+            self.send_event(event);
+        } else {
+            tracing::debug!("Window operation {:?} not allowed", event);
+        }
+    }
+
+    /// XTSAVE
+    fn save_modes(&mut self, params: &[&[u16]]) {
+        for &number in params.iter().filter_map(|p| p.first()) {
+            if let Some(mode) = Mode::from_number(number, true) {
+                self.modes.save(mode);
+            }
+        }
+    }
+
+    /// XTRESTORE
+    fn restore_modes(&mut self, params: &[&[u16]]) {
+        for &number in params.iter().filter_map(|p| p.first()) {
+            if let Some(mode) = Mode::from_number(number, true)
+                && let Some(enabled) = self.modes.saved(mode)
+            {
+                self.set_mode(mode, enabled);
+            }
+        }
+    }
+}
+
+/// Moves a row by the `shift` of a resize, keeping it on a screen of `rows`
+/// lines
+fn shift_row(y: usize, shift: isize, rows: usize) -> usize {
+    y.saturating_add_signed(shift).min(rows - 1)
+}
+
+/// Tab stops every 8 columns
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols)
+        .map(|x| x > 0 && x % TerminalInner::TAB_WIDTH == 0)
+        .collect()
+}
+
+/// Parses the color of SGR 38, 48 and 58, which is either given as
+/// subparameters (`38:2::r:g:b`) or as the parameters that follow
+/// (`38;2;r;g;b`)
+fn parse_extended_color<'a>(
+    subparams: &[u16],
+    params: &mut impl Iterator<Item = &'a &'a [u16]>,
+) -> Option<Color> {
+    let component = |value: u16| value.min(255) as u8;
+    match subparams {
+        // The colon form may include a color space identifier
+        [2, _, r, g, b, ..] | [2, r, g, b] => Some(Color::Rgb(Rgb::new(
+            component(*r),
+            component(*g),
+            component(*b),
+        ))),
+        [5, index, ..] => Some(Color::Indexed(component(*index))),
+        [] => {
+            let mut next = || params.next().and_then(|p| p.first()).copied();
+            match next()? {
+                2 => {
+                    let (r, g, b) = (next()?, next()?, next()?);
+                    Some(Color::Rgb(Rgb::new(
+                        component(r),
+                        component(g),
+                        component(b),
+                    )))
+                }
+                5 => Some(Color::Indexed(component(next()?))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a title or notification sent by the program, dropping control
+/// characters and truncating it to `max_length` characters
+fn sanitize_text(bytes: &[u8], max_length: usize) -> String {
+    String::from_utf8_lossy(bytes)
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(max_length)
+        .collect()
+}
+
+/// Whether the host of a `file://` URI is this machine: empty, `localhost`
+/// or its hostname
+fn is_local_host(host: &str) -> bool {
+    host.is_empty()
+        || host.eq_ignore_ascii_case("localhost")
+        || gethostname::gethostname()
+            .to_str()
+            .is_some_and(|hostname| host.eq_ignore_ascii_case(hostname))
+}
+
+/// Version reported by DA2, as major * 10000 + minor * 100 + patch
+fn version_number() -> u32 {
+    let part = |value: &str| value.parse::<u32>().unwrap_or(0);
+    part(env!("CARGO_PKG_VERSION_MAJOR")) * 10000
+        + part(env!("CARGO_PKG_VERSION_MINOR")) * 100
+        + part(env!("CARGO_PKG_VERSION_PATCH"))
+}
+
+/// Returns the parameter at `index`, or `default` when it is missing or zero
+fn param_or(params: &[&[u16]], index: usize, default: u16) -> u16 {
+    match params.get(index).and_then(|p| p.first()).copied() {
+        Some(0) | None => default,
+        Some(value) => value,
+    }
+}
+
+impl Perform for TerminalInner {
+    fn print(&mut self, c: char) {
+        let c = self.cursor.charsets.map(c);
+        self.last_char = Some(c);
+        self.put_char(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            // Line feed, vertical tab and form feed, which also return the
+            // carriage in LNM
+            b'\n' | b'\x0b' | b'\x0c' => {
+                self.linefeed();
+                if self.modes.get(Mode::LineFeedNewLine) {
+                    self.carriage_return();
+                }
+            }
+            b'\r' => self.carriage_return(),
+            b'\x08' => {
+                // Backspace
+                self.cursor.x = self.cursor.x.saturating_sub(1);
+                self.cursor.pending_wrap = false;
+            }
+            // Horizontal Tab
+            b'\t' => self.forward_tab(1),
+            // Shift Out
+            b'\x0e' => self.cursor.charsets.invoke(1),
+            // Shift In
+            b'\x0f' => self.cursor.charsets.invoke(0),
+            _ => {}
+        }
+        self.dirty = true;
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], ignore: bool, byte: u8) {
+        if ignore {
+            tracing::debug!("Ignoring malformed ESC sequence: {}", byte as char);
+            return;
+        }
+        match (byte, intermediates) {
+            // Index
+            (b'D', []) => self.linefeed(),
+            // Next Line
+            (b'E', []) => self.next_line(),
+            // Reverse Index
+            (b'M', []) => self.reverse_index(),
+            // Horizontal Tab Set
+            (b'H', []) => self.tab_stops[self.cursor.x] = true,
+            // Save Cursor
+            (b'7', []) => self.save_cursor(),
+            // Restore Cursor
+            (b'8', []) => self.restore_cursor(),
+            // Full Reset
+            (b'c', []) => self.reset(),
+            // Application Keypad
+            (b'=', []) => self.modes.set(Mode::KeypadApplication, true),
+            // Normal Keypad
+            (b'>', []) => self.modes.set(Mode::KeypadApplication, false),
+            // Locking Shifts 2 and 3
+            (b'n', []) => self.cursor.charsets.invoke(2),
+            (b'o', []) => self.cursor.charsets.invoke(3),
+            // Single Shifts 2 and 3
+            (b'N', []) => self.cursor.charsets.single_shift(2),
+            (b'O', []) => self.cursor.charsets.single_shift(3),
+            // Designate G0–G3
+            (_, [intermediate @ (b'(' | b')' | b'*' | b'+')]) => {
+                let index = (intermediate - b'(') as usize;
+                match Charset::from_designator(byte) {
+                    Some(charset) => self.cursor.charsets.designate(index, charset),
+                    None => tracing::debug!("Unsupported character set: {}", byte as char),
+                }
+            }
+            _ => tracing::debug!(
+                "Unhandled ESC sequence: {} with intermediates: {:?}",
+                byte as char,
+                intermediates
+            ),
+        }
+        self.dirty = true;
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], bell_terminated: bool) {
+        let Some((command, args)) = params.split_first() else {
+            return;
+        };
+        match *command {
+            // Set icon name and window title, icon name, or window title
+            b"0" | b"1" | b"2" => {
+                // The title may itself contain semicolons
+                let title = sanitize_text(&args.join(&b';'), Self::MAX_TITLE_LENGTH);
+                if *command != b"2" {
+                    self.titles.icon = title.clone();
+                }
+                if *command != b"1" {
+                    self.set_window_title(title);
+                }
+            }
+            // Current working directory
+            b"7" => self.set_current_dir(args),
+            // Hyperlink
+            b"8" => self.set_hyperlink(args),
+            // Progress report, or desktop notification
+            b"9" => match args {
+                [b"4", args @ ..] => self.handle_progress(args),
+                _ => self.handle_iterm_notification(args),
+            },
+            // Manipulate Selection Data
+            b"52" => self.handle_clipboard(args, bell_terminated),
+            // Desktop notification
+            b"99" => self.handle_kitty_notification(args),
+            // Semantic prompt marks
+            b"133" => self.set_semantic_mark(args),
+            // Desktop notification
+            b"777" => self.handle_urxvt_notification(args),
+            _ => tracing::debug!(
+                "Unhandled OSC {}: {:?}",
+                String::from_utf8_lossy(command),
+                args
+            ),
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], ignore: bool, c: char) {
+        if ignore {
+            tracing::debug!("Ignoring malformed CSI sequence: {}", c);
+            return;
+        }
+        let params: Vec<&[u16]> = params.iter().collect();
+        // Handle some common CSI sequences
+        match (c, intermediates) {
+            ('H' | 'f', []) => {
+                // Cursor Position
+                let row = param_or(&params, 0, 1) as usize;
+                let col = param_or(&params, 1, 1) as usize;
+                self.goto(col - 1, row - 1);
+            }
+            // Erase in Display
+            ('J', []) => self.erase_in_display(param_or(&params, 0, 0)),
+            // Erase in Line
+            ('K', []) => self.erase_in_line(param_or(&params, 0, 0)),
+            // Insert Characters
+            ('@', []) => self.insert_chars(param_or(&params, 0, 1) as usize),
+            // Delete Characters
+            ('P', []) => self.delete_chars(param_or(&params, 0, 1) as usize),
+            // Erase Characters
+            ('X', []) => self.erase_chars(param_or(&params, 0, 1) as usize),
+            // Insert Lines
+            ('L', []) => self.insert_or_delete_lines(param_or(&params, 0, 1) as usize, true),
+            // Delete Lines
+            ('M', []) => self.insert_or_delete_lines(param_or(&params, 0, 1) as usize, false),
+            // Repeat
+            ('b', []) => self.repeat_char(param_or(&params, 0, 1) as usize),
+            // Cursor Character Absolute, Horizontal Position Absolute
+            ('G' | '`', []) => self.goto_column(param_or(&params, 0, 1) as usize - 1),
+            // Vertical Position Absolute
+            ('d', []) => self.goto_row(param_or(&params, 0, 1) as usize - 1),
+            // Horizontal Position Relative
+            ('a', []) => {
+                let count = param_or(&params, 0, 1) as usize;
+                self.move_cursor(self.cursor.x.saturating_add(count), self.cursor.y);
+            }
+            // Vertical Position Relative
+            ('e', []) => {
+                let count = param_or(&params, 0, 1) as usize;
+                self.move_cursor(self.cursor.x, self.cursor.y.saturating_add(count));
+            }
+            // Cursor Next Line
+            ('E', []) => {
+                self.move_cursor_vertically(param_or(&params, 0, 1) as isize);
+                self.carriage_return();
+            }
+            // Cursor Previous Line
+            ('F', []) => {
+                self.move_cursor_vertically(-(param_or(&params, 0, 1) as isize));
+                self.carriage_return();
+            }
+            // Cursor Horizontal Forward Tabulation
+            ('I', []) => self.forward_tab(param_or(&params, 0, 1) as usize),
+            // Cursor Backward Tabulation
+            ('Z', []) => self.backward_tab(param_or(&params, 0, 1) as usize),
+            // Tab Clear
+            ('g', []) => self.clear_tab_stops(param_or(&params, 0, 0)),
+            // Scroll Up
+            ('S', []) => self.scroll_up(param_or(&params, 0, 1) as usize),
+            // Scroll Down
+            ('T', []) if params.len() <= 1 => self.scroll_down(param_or(&params, 0, 1) as usize),
+            // Window Manipulation
+            ('t', []) => self.window_op(&params),
+            // Set Top and Bottom Margins
+            ('r', []) => {
+                let top = param_or(&params, 0, 1) as usize;
+                let bottom = param_or(&params, 1, self.size.rows) as usize;
+                self.set_scroll_region(top, bottom);
+            }
+            // Set Left and Right Margins
+            ('s', []) if self.modes.get(Mode::LeftRightMargin) => {
+                let left = param_or(&params, 0, 1) as usize;
+                let right = param_or(&params, 1, self.size.cols) as usize;
+                self.set_margins(left, right);
+            }
+            // Select Graphic Rendition
+            ('m', []) => self.set_graphics_rendition(&params),
+            // XTMODKEYS, only modifyOtherKeys is supported. A missing
+            // value resets it.
+            ('m', [b'>']) if param_or(&params, 0, 0) == 4 => {
+                self.modify_other_keys = param_or(&params, 1, 0).min(2) as u8;
+            }
+            ('n', [b'>']) if param_or(&params, 0, 0) == 4 => self.modify_other_keys = 0,
+            // XTQMODKEYS
+            ('m', [b'?']) if param_or(&params, 0, 0) == 4 => {
+                let response = format!("\x1b[>4;{}m", self.modify_other_keys);
+                self.write(response.as_bytes());
+            }
+            // Save and restore the cursor, when the left and right margins
+            // aren't enabled
+            ('s', []) => self.save_cursor(),
+            ('u', []) => self.restore_cursor(),
+            // Set Mode / Reset Mode
+            ('h' | 'l', []) => self.set_modes(&params, false, c == 'h'),
+            // DECSET / DECRST
+            ('h' | 'l', [b'?']) => self.set_modes(&params, true, c == 'h'),
+            // Request Mode
+            ('p', [b'$']) => self.report_mode(param_or(&params, 0, 0), false),
+            ('p', [b'?', b'$']) => self.report_mode(param_or(&params, 0, 0), true),
+            // XTSAVE / XTRESTORE
+            ('s', [b'?']) => self.save_modes(&params),
+            ('r', [b'?']) => self.restore_modes(&params),
+            // Device Status Report
+            ('n', []) => self.device_status_report(param_or(&params, 0, 0), false),
+            ('n', [b'?']) => self.device_status_report(param_or(&params, 0, 0), true),
+            // Primary Device Attributes
+            ('c', []) if param_or(&params, 0, 0) == 0 => self.write(b"\x1b[?62;22c"),
+            // Secondary Device Attributes, with the version as a number
+            ('c', [b'>']) if param_or(&params, 0, 0) == 0 => {
+                let response = format!("\x1b[>1;{};0c", version_number());
+                self.write(response.as_bytes());
+            }
+            // Tertiary Device Attributes, with a zero unit ID
+            ('c', [b'=']) if param_or(&params, 0, 0) == 0 => self.write(b"\x1bP!|00000000\x1b\\"),
+            // XTVERSION
+            ('q', [b'>']) if param_or(&params, 0, 0) == 0 => {
+                let response = format!(
+                    "\x1bP>|{}({})\x1b\\",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
                 );
+                self.write(response.as_bytes());
+            }
+            // Kitty keyboard protocol: push, pop, set and query the flags
+            ('u', [b'>']) => {
+                let flags = KeyboardFlags::from_bits_truncate(param_or(&params, 0, 0) as u8);
+                self.keyboard_flags.push(flags);
+            }
+            ('u', [b'<']) => self.keyboard_flags.pop(param_or(&params, 0, 1) as usize),
+            ('u', [b'=']) => {
+                let flags = KeyboardFlags::from_bits_truncate(param_or(&params, 0, 0) as u8);
+                self.keyboard_flags.set(flags, param_or(&params, 1, 1));
+            }
+            ('u', [b'?']) => {
+                let response = format!("\x1b[?{}u", self.keyboard_flags.current().bits());
+                self.write(response.as_bytes());
+            }
+            // Cursor Up
+            ('A', []) => {
+                let count = param_or(&params, 0, 1) as usize;
+                tracing::debug!("Cursor Up by {}, other params: {:?}", count, params);
+                self.move_cursor_vertically(-(count as isize));
+            }
+            // Cursor Down
+            ('B', []) => {
+                let count = param_or(&params, 0, 1) as usize;
+                tracing::debug!("Cursor Down by {}, other params: {:?}", count, params);
+                self.move_cursor_vertically(count as isize);
+            }
+            // Cursor Right
+            ('C', []) => {
+                let count = param_or(&params, 0, 1) as usize;
                 tracing::debug!("Cursor Right by {}, other params: {:?}", count, params);
-                self.move_cursor(self.cursor_x.saturating_add(count), self.cursor_y);
+                self.move_cursor_horizontally(count as isize);
             }
             // Cursor Left
-            'D' => {
-                let count = max(
-                    1,
-                    params.get(0).and_then(|p| p.first()).copied().unwrap_or(1) as usize,
-                );
+            ('D', []) => {
+                let count = param_or(&params, 0, 1) as usize;
                 tracing::debug!("Cursor Left by {}, other params: {:?}", count, params);
-                self.move_cursor(self.cursor_x.saturating_sub(count), self.cursor_y);
+                self.move_cursor_horizontally(-(count as isize));
             }
             _ => {
-                tracing::debug!("Unhandled CSI sequence: {} with params: {:?}", c, params);
+                tracing::debug!(
+                    "Unhandled CSI sequence: {} with params: {:?}, intermediates: {:?}",
+                    c,
+                    params,
+                    intermediates
+                );
                 // Ignore other CSI sequences for now
             }
         }
         self.dirty = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::Clipboard;
+    use crate::clipboard::MemoryClipboard;
+    use crate::notify::MemoryNotifier;
+
+    /// A terminal without a PTY, keeping what it writes to the program
+    struct TestTerminal {
+        terminal: TerminalInner,
+        output: Receiver<Vec<u8>>,
+        clipboard: Arc<Mutex<MemoryClipboard>>,
+        notifier: Arc<Mutex<MemoryNotifier>>,
+    }
+
+    impl TestTerminal {
+        fn new(config: Config) -> Self {
+            let (writer, output) = crossbeam_channel::unbounded();
+            let (events, _) = crossbeam_channel::unbounded();
+            let clipboard = Arc::new(Mutex::new(MemoryClipboard::default()));
+            let notifier = Arc::new(Mutex::new(MemoryNotifier::default()));
+            Self {
+                terminal: TerminalInner::new(
+                    writer,
+                    config,
+                    events,
+                    clipboard.clone(),
+                    notifier.clone(),
+                ),
+                output,
+                clipboard,
+                notifier,
+            }
+        }
+
+        fn feed(&mut self, bytes: &[u8]) {
+            self.terminal.feed_bytes(bytes);
+        }
+
+        /// Everything written to the program since the last call
+        fn output(&self) -> Vec<u8> {
+            self.output.try_iter().flatten().collect()
+        }
+
+        /// Text of the lines on screen
+        fn screen(&self) -> Vec<String> {
+            (0..self.terminal.rows())
+                .map(|y| self.terminal.grid.visible_row(y).unwrap().text())
+                .collect()
+        }
+
+        /// Notifications shown since the last call
+        fn notifications(&self) -> Vec<Notification> {
+            std::mem::take(&mut self.notifier.lock().unwrap().notifications)
+        }
+
+        fn clipboard(&self, selection: Selection) -> String {
+            self.clipboard.lock().unwrap().get(selection).unwrap()
+        }
+    }
+
+    #[test]
+    fn print_into_cells() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 3 });
+        t.feed(b"1\r\n2\r\n3\r\n4");
+        assert_eq!(t.screen(), ["2", "3", "4"]);
+        assert_eq!(t.terminal.grid.line(0).text(), "1");
+
+        // Wide characters take two cells
+        t.feed("\x1b[H\u{5b57}x".as_bytes());
+        let row = t.terminal.grid.visible_row(0).unwrap();
+        assert_eq!(row[0].c, '\u{5b57}');
+        assert!(row[1].is_spacer());
+        assert_eq!(row[2].c, 'x');
+        // Overwriting half of one blanks the other half
+        t.feed(b"\x08\x08y");
+        assert_eq!(t.screen()[0], " yx");
+
+        t.feed(b"\x1b[2;2H\x1b[K\x1b[3;1H\x1b[1K");
+        assert_eq!(t.screen(), [" yx", "3", ""]);
+        t.feed(b"\x1b[2J");
+        assert_eq!(t.screen(), ["", "", ""]);
+        assert_eq!(t.terminal.grid.line(0).text(), "1");
+    }
+
+    #[test]
+    fn autowrap() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 3 });
+        // The wrap is deferred until the next character
+        t.feed(b"abcde");
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (4, 0));
+        assert!(t.terminal.cursor.pending_wrap);
+        t.feed(b"f");
+        assert_eq!(t.screen(), ["abcde", "f", ""]);
+        assert!(t.terminal.grid.visible_row(0).unwrap().is_wrapped());
+
+        // A carriage return cancels it
+        t.feed(b"\x1b[Habcde\rx");
+        assert_eq!(t.screen(), ["xbcde", "f", ""]);
+
+        // A wide character that doesn't fit goes on the next line
+        t.feed("\x1b[3;1Habcd\u{5b57}".as_bytes());
+        assert_eq!(t.screen(), ["f", "abcd", "\u{5b57}"]);
+        assert!(t.terminal.grid.visible_row(1).unwrap().is_wrapped());
+    }
+
+    #[test]
+    fn autowrap_off() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 2 });
+        t.feed(b"\x1b[?7labcdefg");
+        assert_eq!(t.screen(), ["abcdg", ""]);
+        t.feed(b"\x1b[?7h\r\nabcdefg");
+        assert_eq!(t.screen(), ["abcde", "fg"]);
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 5 });
+        t.feed(b"1\r\n2\r\n3\r\n4\r\n5");
+        // DECSTBM homes the cursor
+        t.feed(b"\x1b[2;4r");
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (0, 0));
+        t.feed(b"\x1b[4;2Hx\n");
+        assert_eq!(t.screen(), ["1", "3", "4x", "", "5"]);
+        // Lines scrolled out of a region don't go to the scrollback
+        assert_eq!(t.terminal.grid.total_lines(), 5);
+
+        t.feed(b"\x1b[2T");
+        assert_eq!(t.screen(), ["1", "", "", "3", "5"]);
+        t.feed(b"\x1b[S");
+        assert_eq!(t.screen(), ["1", "", "3", "", "5"]);
+        // Reverse index at the top of the region scrolls it down
+        t.feed(b"\x1b[2H\x1bM");
+        assert_eq!(t.screen(), ["1", "", "", "3", "5"]);
+    }
+
+    #[test]
+    fn scroll_margins() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 3 });
+        t.feed(b"abcde\r\nfghij\r\nklmno");
+        // DECSLRM needs DECLRMM
+        t.feed(b"\x1b[2;4s");
+        assert_eq!(t.terminal.margins, 0..5);
+        t.feed(b"\x1b[?69h\x1b[2;4s\x1b[S");
+        assert_eq!(t.screen(), ["aghie", "flmnj", "k   o"]);
+        t.feed(b"\x1b[3;4Hx\r");
+        assert_eq!(t.terminal.cursor.x, 1);
+        // Resetting DECLRMM drops the margins
+        t.feed(b"\x1b[?69l");
+        assert_eq!(t.terminal.margins, 0..5);
+    }
+
+    #[test]
+    fn alt_screen() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 2 });
+        t.feed(b"main");
+        // 47 keeps what was on the alternate screen
+        t.feed(b"\x1b[?47h\x1b[Halt\x1b[?47l");
+        assert_eq!(t.screen(), ["main", ""]);
+        t.feed(b"\x1b[?47h");
+        assert_eq!(t.screen(), ["alt", ""]);
+        // 1047 clears it on the way out
+        t.feed(b"\x1b[?47l\x1b[?1047h\x1b[?1047l\x1b[?1047h");
+        assert_eq!(t.screen(), ["", ""]);
+        // The alternate screen has no scrollback
+        t.feed(b"1\r\n2\r\n3");
+        assert_eq!(t.terminal.grid.total_lines(), 2);
+        t.feed(b"\x1b[?1047l");
+        assert_eq!(t.screen(), ["main", ""]);
+    }
+
+    #[test]
+    fn alt_screen_save_cursor() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 2 });
+        t.feed(b"\x1b[?47h\x1b[Halt\x1b[?47l\x1b[2;3H");
+        // 1049 saves the cursor and clears the alternate screen
+        t.feed(b"\x1b[?1049h");
+        assert_eq!(t.screen(), ["", ""]);
+        t.feed(b"\x1b[Hvim\x1b[?1049l");
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (2, 1));
+
+        // 1048 saves the cursor alone
+        t.feed(b"\x1b[?1048h\x1b[H\x1b[?1048l");
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (2, 1));
+    }
+
+    #[test]
+    fn graphics_rendition() {
+        let mut t = TestTerminal::new(Config::default());
+        let attrs = |t: &mut TestTerminal, sgr: &str| {
+            t.feed(format!("\x1b[0m\x1b[{}m", sgr).as_bytes());
+            t.terminal.cursor.attrs.clone()
+        };
+
+        let a = attrs(&mut t, "1;3;4;9;31;42");
+        assert_eq!(
+            a.flags,
+            Flags::BOLD | Flags::ITALIC | Flags::UNDERLINE | Flags::STRIKEOUT
+        );
+        assert_eq!((a.fg, a.bg), (Color::Indexed(1), Color::Indexed(2)));
+        let a = attrs(&mut t, "1;2;22;4;24;7;27;39");
+        assert_eq!((a.flags, a.fg), (Flags::empty(), Color::Default));
+        assert_eq!(attrs(&mut t, "4:3").flags, Flags::UNDERLINE);
+        assert_eq!(attrs(&mut t, "4;21").flags, Flags::DOUBLE_UNDERLINE);
+        assert_eq!(attrs(&mut t, "4:2;4:0").flags, Flags::empty());
+
+        // Bright colors, the 256-color palette and direct colors, with
+        // semicolons or colons
+        assert_eq!(attrs(&mut t, "97").fg, Color::Indexed(15));
+        assert_eq!(attrs(&mut t, "101").bg, Color::Indexed(9));
+        assert_eq!(attrs(&mut t, "38;5;200").fg, Color::Indexed(200));
+        assert_eq!(attrs(&mut t, "48:5:17").bg, Color::Indexed(17));
+        let rgb = Color::Rgb(Rgb::new(1, 2, 3));
+        assert_eq!(attrs(&mut t, "38;2;1;2;3").fg, rgb);
+        assert_eq!(attrs(&mut t, "38:2::1:2:3").fg, rgb);
+        assert_eq!(attrs(&mut t, "48:2:1:2:3").bg, rgb);
+        // The parameters of an underline color don't leak into the others
+        assert_eq!(attrs(&mut t, "58;5;1;1").flags, Flags::BOLD);
+        assert_eq!(attrs(&mut t, "58;2;1;2;3;32").fg, Color::Indexed(2));
+
+        // Printed cells take the attributes
+        t.feed(b"\x1b[0;7mx\x1b[my");
+        let row = t.terminal.grid.visible_row(0).unwrap();
+        assert_eq!(row[0].attrs.flags, Flags::INVERSE);
+        assert_eq!(row[1].attrs.flags, Flags::empty());
+    }
+
+    #[test]
+    fn save_and_restore_cursor() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 5 });
+        // Nothing saved yet, DECRC homes the cursor
+        t.feed(b"\x1b[3;3H\x1b8");
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (0, 0));
+
+        // The position, attributes, charsets and origin mode are saved
+        t.feed(b"\x1b[2;4r\x1b[?6h\x1b[2;3H\x1b[1m\x1b(0\x1b7");
+        t.feed(b"\x1b[?6l\x1b[5;5H\x1b[m\x1b(B\x1b8q");
+        assert!(t.terminal.modes.get(Mode::Origin));
+        assert_eq!((t.terminal.cursor.x, t.terminal.cursor.y), (3, 2));
+        let row = t.terminal.grid.visible_row(2).unwrap();
+        assert_eq!(row[2].c, '\u{2500}');
+        assert_eq!(row[2].attrs.flags, Flags::BOLD);
+    }
+
+    #[test]
+    fn index_and_keypad() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 3 });
+        t.feed(b"ab\x1bDc\x1bEd\x1bEe");
+        assert_eq!(t.screen(), ["  c", "d", "e"]);
+        t.feed(b"\x1b[H\x1bM");
+        assert_eq!(t.screen(), ["", "  c", "d"]);
+
+        t.feed(b"\x1b=");
+        assert!(t.terminal.modes.get(Mode::KeypadApplication));
+        t.feed(b"\x1b>");
+        assert!(!t.terminal.modes.get(Mode::KeypadApplication));
+    }
+
+    #[test]
+    fn line_drawing() {
+        let mut t = TestTerminal::new(Config::default());
+        // SO and SI switch between G1 and G0
+        t.feed(b"\x1b)0lq\x0elqk\x0fk");
+        assert_eq!(t.screen()[0], "lq\u{250c}\u{2500}\u{2510}k");
+        // G2 and G3 through single and locking shifts
+        t.feed(b"\r\n\x1b*A\x1b+0\x1bN##\x1bo`\x1b(B\x0f`");
+        assert_eq!(t.screen()[1], "\u{a3}#\u{25c6}`");
+    }
+
+    #[test]
+    fn character_editing() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 8, rows: 2 });
+        t.feed(b"abcdefgh\x1b[1;3H\x1b[2@");
+        assert_eq!(t.screen()[0], "ab  cdef");
+        t.feed(b"\x1b[3P");
+        assert_eq!(t.screen()[0], "abdef");
+        t.feed(b"\x1b[1;2H\x1b[2X");
+        assert_eq!(t.screen()[0], "a  ef");
+        // Counts past the end of the line stop there
+        t.feed(b"\x1b[1;4H\x1b[99P");
+        assert_eq!(t.screen()[0], "a");
+        // Inside the margins, cells move between them only
+        t.feed(b"\x1b[2;1Habcdefgh\x1b[?69h\x1b[2;5s\x1b[2;3H\x1b[@");
+        assert_eq!(t.screen()[1], "ab cdfgh");
+    }
+
+    #[test]
+    fn line_editing() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 5 });
+        t.feed(b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r");
+        t.feed(b"\x1b[3H\x1b[L");
+        assert_eq!(t.screen(), ["1", "2", "", "3", "5"]);
+        t.feed(b"\x1b[2M");
+        assert_eq!(t.screen(), ["1", "2", "", "", "5"]);
+        // Outside the scroll region IL and DL do nothing
+        t.feed(b"\x1b[5H\x1b[L\x1b[1H\x1b[M");
+        assert_eq!(t.screen(), ["1", "2", "", "", "5"]);
+    }
+
+    #[test]
+    fn repeat() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 5, rows: 3 });
+        t.feed(b"ab\x1b[5b");
+        assert_eq!(t.screen(), ["abbbb", "bb", ""]);
+        // Nothing to repeat after a control
+        t.feed(b"\x1b[3H\x1b[b");
+        assert_eq!(t.screen(), ["abbbb", "bb", "b"]);
+    }
+
+    #[test]
+    fn cursor_positioning() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 5 });
+        let cursor = |t: &mut TestTerminal, sequence: &str| {
+            t.feed(sequence.as_bytes());
+            (t.terminal.cursor.x, t.terminal.cursor.y)
+        };
+        assert_eq!(cursor(&mut t, "\x1b[3;3H\x1b[5G"), (4, 2));
+        assert_eq!(cursor(&mut t, "\x1b[7`"), (6, 2));
+        assert_eq!(cursor(&mut t, "\x1b[2d"), (6, 1));
+        assert_eq!(cursor(&mut t, "\x1b[2a"), (8, 1));
+        assert_eq!(cursor(&mut t, "\x1b[9a"), (9, 1));
+        assert_eq!(cursor(&mut t, "\x1b[2e"), (9, 3));
+        assert_eq!(cursor(&mut t, "\x1b[E"), (0, 4));
+        assert_eq!(cursor(&mut t, "\x1b[4;4H\x1b[2F"), (0, 1));
+        assert_eq!(cursor(&mut t, "\x1b[9F"), (0, 0));
+        assert_eq!(cursor(&mut t, "\x1b[99;99H"), (9, 4));
+    }
+
+    #[test]
+    fn hyperlinks() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]8;;http://a\x07ab\x1b]8;;\x07 \x1b]8;;http://a\x07c\x1b]8;;\x07 ");
+        t.feed(b"\x1b]8;id=1;http://b\x07d\x1b]8;;\x07 \x1b]8;id=1;http://b\x07e\x1b]8;;\x07");
+        let row = t.terminal.grid.visible_row(0).unwrap();
+        let link = |x: usize| row[x].attrs.hyperlink.clone().unwrap();
+        assert_eq!(link(0).uri, "http://a");
+        assert!(row[2].attrs.hyperlink.is_none());
+        // Without an id, only the cells of the same OSC 8 are the same link
+        assert!(Hyperlink::same_link(&link(0), &link(1)));
+        assert!(!Hyperlink::same_link(&link(0), &link(3)));
+        // With one, every cell with the same id and URI is
+        assert!(Hyperlink::same_link(&link(5), &link(7)));
+    }
+
+    #[test]
+    fn resize_on_alt_screen() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 3 });
+        t.feed(b"1\r\n2\r\n3\r\n4\r\n5");
+        t.feed(b"\x1b[?1049h\x1b[Hvim");
+        // Growing pulls lines back from the scrollback, above the saved
+        // cursor
+        t.terminal.resize(Size { cols: 10, rows: 5 });
+        t.feed(b"\x1b[?1049lx");
+        assert_eq!(t.screen(), ["1", "2", "3", "4", "5x"]);
+
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 5 });
+        t.feed(b"a\r\nb");
+        t.feed(b"\x1b[?1049h\x1b[5Hvim");
+        // The blank lines below the primary cursor are dropped first, even
+        // though the alternate cursor is at the bottom
+        t.terminal.resize(Size { cols: 10, rows: 3 });
+        t.feed(b"\x1b[?1049lc");
+        assert_eq!(t.screen(), ["a", "bc", ""]);
+    }
+
+    #[test]
+    fn modes() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[4h\x1b[?1;2004h\x1b[?7l");
+        assert!(t.terminal.modes.get(Mode::Insert));
+        assert!(t.terminal.modes.get(Mode::CursorKeys));
+        assert!(t.terminal.modes.get(Mode::BracketedPaste));
+        assert!(!t.terminal.modes.get(Mode::AutoWrap));
+        // ANSI and DEC private modes have separate numbers
+        t.feed(b"\x1b[?4h\x1b[1l");
+        assert!(t.terminal.modes.get(Mode::CursorKeys));
+
+        // DECRQM answers 1 for set, 2 for reset and 0 for unknown modes
+        t.feed(b"\x1b[4$p\x1b[?7$p\x1b[?2004$p\x1b[?1007$p\x1b[?12$p\x1b[99$p");
+        assert_eq!(
+            t.output(),
+            b"\x1b[4;1$y\x1b[?7;2$y\x1b[?2004;1$y\x1b[?1007;0$y\x1b[?12;0$y\x1b[99;0$y"
+        );
+    }
+
+    #[test]
+    fn save_and_restore_modes() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[?2004h\x1b[?2004;7s\x1b[?2004;7l");
+        assert!(!t.terminal.modes.get(Mode::BracketedPaste));
+        t.feed(b"\x1b[?2004;7r");
+        assert!(t.terminal.modes.get(Mode::BracketedPaste));
+        assert!(t.terminal.modes.get(Mode::AutoWrap));
+        // Modes that weren't saved are left alone
+        t.feed(b"\x1b[?1h\x1b[?1r");
+        assert!(t.terminal.modes.get(Mode::CursorKeys));
+    }
+
+    #[test]
+    fn status_reports() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 20, rows: 10 });
+        t.feed(b"\x1b[5n\x1b[3;4H\x1b[6n\x1b[?6n");
+        assert_eq!(t.output(), b"\x1b[0n\x1b[3;4R\x1b[?3;4;1R");
+        // In origin mode the position is relative to the margins
+        t.feed(b"\x1b[2;8r\x1b[?69h\x1b[3;15s\x1b[?6h\x1b[2;2H\x1b[6n");
+        assert_eq!(t.output(), b"\x1b[2;2R");
+        // Unknown requests aren't answered
+        t.feed(b"\x1b[?5n\x1b[7n");
+        assert_eq!(t.output(), b"");
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[>4;1m\x1b[?4m");
+        assert_eq!(t.output(), b"\x1b[>4;1m");
+        // Levels past 2 are taken as 2
+        t.feed(b"\x1b[>4;3m\x1b[?4m");
+        assert_eq!(t.output(), b"\x1b[>4;2m");
+        t.feed(b"\x1b[>4m\x1b[?4m\x1b[>4;2m\x1b[>4n\x1b[?4m");
+        assert_eq!(t.output(), b"\x1b[>4;0m\x1b[>4;0m");
+        // Other resources aren't supported
+        t.feed(b"\x1b[>1;2m\x1b[?4m");
+        assert_eq!(t.output(), b"\x1b[>4;0m");
+    }
+
+    #[test]
+    fn keyboard_flags() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[>1u\x1b[>5u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?5u");
+        t.feed(b"\x1b[=8;2u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?13u");
+        t.feed(b"\x1b[=4;3u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?9u");
+        t.feed(b"\x1b[=2u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?2u");
+        t.feed(b"\x1b[<u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?1u");
+
+        // Each screen has its own flags
+        t.feed(b"\x1b[?1049h");
+        assert!(t.terminal.keyboard_flags.current().is_empty());
+        t.feed(b"\x1b[>31u\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?31u");
+        t.feed(b"\x1b[?1049l\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?1u");
+        t.feed(b"\x1b[?1049h\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?31u");
+        // Popping everything resets the flags of the active screen only
+        t.feed(b"\x1b[<10u\x1b[?u\x1b[?1049l\x1b[?u");
+        assert_eq!(t.output(), b"\x1b[?0u\x1b[?1u");
+    }
+
+    #[test]
+    fn device_attributes() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[c\x1b[0c");
+        assert_eq!(t.output(), b"\x1b[?62;22c\x1b[?62;22c");
+        t.feed(b"\x1b[>c");
+        let expected = format!("\x1b[>1;{};0c", version_number());
+        assert_eq!(t.output(), expected.as_bytes());
+        t.feed(b"\x1b[=c");
+        assert_eq!(t.output(), b"\x1bP!|00000000\x1b\\");
+        t.feed(b"\x1b[>q");
+        let expected = format!("\x1bP>|cosmicterm({})\x1b\\", env!("CARGO_PKG_VERSION"));
+        assert_eq!(t.output(), expected.as_bytes());
+        t.feed(b"\x1b[1c\x1b[>1c");
+        assert_eq!(t.output(), b"");
+    }
+
+    #[test]
+    fn alt_screen_mode_report() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[?47$p");
+        assert_eq!(t.output(), b"\x1b[?47;2$y");
+        t.feed(b"\x1b[?1049h\x1b[?47$p\x1b[?1047$p\x1b[?1049$p");
+        assert_eq!(t.output(), b"\x1b[?47;1$y\x1b[?1047;1$y\x1b[?1049;1$y");
+        t.feed(b"\x1b[?1049l\x1b[?1049$p");
+        assert_eq!(t.output(), b"\x1b[?1049;2$y");
+    }
+
+    #[test]
+    fn full_reset() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 3 });
+        t.terminal.cell_size = CellSize {
+            width: 8,
+            height: 16,
+        };
+        t.terminal.set_focused(true);
+        t.feed(b"\x1b]2;title\x07\x1b]7;file:///tmp\x07\x1b[22t");
+        t.feed(b"\x1b[?1049h\x1b[?25l\x1b[>1u\x1b[>4;2m\x1b[3g\x1b[2;3r\x1b[1mtext");
+        t.feed(b"\x1bc");
+
+        let terminal = &t.terminal;
+        assert_eq!(t.screen(), ["", "", ""]);
+        assert!(!terminal.alt_screen);
+        assert_eq!((terminal.cursor.x, terminal.cursor.y), (0, 0));
+        assert_eq!(terminal.cursor.attrs, Attributes::default());
+        assert!(terminal.modes.get(Mode::ShowCursor));
+        assert!(terminal.keyboard_flags.current().is_empty());
+        assert_eq!(terminal.modify_other_keys, 0);
+        assert_eq!(terminal.tab_stops, default_tab_stops(10));
+        assert_eq!(terminal.scroll_region, 0..3);
+        assert!(terminal.title_stack.is_empty());
+        // What doesn't belong to the emulation is kept
+        assert_eq!(terminal.size, Size { cols: 10, rows: 3 });
+        assert_eq!(terminal.cell_size.height, 16);
+        assert_eq!(terminal.titles.window, "title");
+        assert_eq!(terminal.current_dir, Some(PathBuf::from("/tmp")));
+        assert!(terminal.focused);
+    }
+
+    #[test]
+    fn tab_stops() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 20, rows: 2 });
+        let x = |t: &mut TestTerminal, sequence: &[u8]| {
+            t.feed(sequence);
+            t.terminal.cursor.x
+        };
+        assert_eq!(x(&mut t, b"\t"), 8);
+        assert_eq!(x(&mut t, b"\t\t"), 19);
+        // HTS sets a stop, TBC clears the one at the cursor or all of them
+        assert_eq!(x(&mut t, b"\x1b[4G\x1bH\r\t"), 3);
+        assert_eq!(x(&mut t, b"\t"), 8);
+        assert_eq!(x(&mut t, b"\x1b[g\r\x1b[2I"), 16);
+        assert_eq!(x(&mut t, b"\x1b[Z"), 3);
+        assert_eq!(x(&mut t, b"\x1b[9Z"), 0);
+        assert_eq!(x(&mut t, b"\x1b[3g\t"), 19);
+        // Tabs don't print anything
+        assert_eq!(t.screen()[0], "");
+    }
+
+    #[test]
+    fn tab_stops_at_right_margin() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b[?69h\x1b[1;20s\t\t");
+        assert_eq!(t.terminal.cursor.x, 16);
+        t.feed(b"\t\t");
+        assert_eq!(t.terminal.cursor.x, 19);
+        // Past the right margin, tabs go on to the last column
+        t.feed(b"\x1b[1;26H\t");
+        assert_eq!(t.terminal.cursor.x, 32);
+        t.feed(b"\x1b[20I");
+        assert_eq!(t.terminal.cursor.x, 79);
+    }
+
+    #[test]
+    fn title_stack() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]2;window\x07\x1b]1;icon\x07");
+        // Push the window title only, then both
+        t.feed(b"\x1b[22;2t\x1b]0;other\x07\x1b[22t\x1b]0;last\x07");
+        t.feed(b"\x1b[23t");
+        assert_eq!(t.terminal.titles.window, "other");
+        assert_eq!(t.terminal.titles.icon, "other");
+        t.feed(b"\x1b[23t");
+        assert_eq!(t.terminal.titles.window, "window");
+        assert_eq!(t.terminal.titles.icon, "other");
+
+        // Pop the icon title only
+        t.feed(b"\x1b[22t\x1b]0;new\x07\x1b[23;1t");
+        assert_eq!(t.terminal.titles.window, "new");
+        assert_eq!(t.terminal.titles.icon, "other");
+    }
+
+    #[test]
+    fn current_dir_on_this_host() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]7;file:///tmp/a%20b\x07");
+        assert_eq!(t.terminal.current_dir, Some(PathBuf::from("/tmp/a b")));
+        t.feed(b"\x1b]7;file://localhost/usr\x07");
+        assert_eq!(t.terminal.current_dir, Some(PathBuf::from("/usr")));
+        let hostname = gethostname::gethostname().into_string().unwrap();
+        t.feed(format!("\x1b]7;file://{}/home\x07", hostname).as_bytes());
+        assert_eq!(t.terminal.current_dir, Some(PathBuf::from("/home")));
+        t.feed(b"\x1b]7;file://remote.invalid/tmp\x07");
+        assert_eq!(t.terminal.current_dir, None);
+    }
+
+    #[test]
+    fn last_command_output() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 10, rows: 10 });
+        let prompt = b"\x1b]133;A\x07$ \x1b]133;B\x07";
+        assert_eq!(t.terminal.last_command_output(), None);
+
+        t.feed(prompt);
+        t.feed(b"ls\r\n\x1b]133;C\x07a\r\nlong line b\r\n\x1b]133;D;0\x07");
+        t.feed(prompt);
+        assert_eq!(
+            t.terminal.last_command_output().as_deref(),
+            Some("a\nlong line b")
+        );
+
+        // Output without a trailing newline ends where the prompt starts
+        t.feed(b"printf foo\r\n\x1b]133;C\x07foo\x1b]133;D;0\x07");
+        t.feed(prompt);
+        assert_eq!(t.terminal.last_command_output().as_deref(), Some("foo"));
+        t.feed(b"printf 'a\\nb'\r\n\x1b]133;C\x07a\r\nb\x1b]133;D;0\x07");
+        t.feed(prompt);
+        assert_eq!(t.terminal.last_command_output().as_deref(), Some("a\nb"));
+
+        t.feed(b"true\r\n\x1b]133;C\x07\x1b]133;D;0\x07");
+        t.feed(prompt);
+        assert_eq!(t.terminal.last_command_output().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn prompt_jumps_with_full_scrollback() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.resize(Size { cols: 20, rows: 4 });
+        // Fill the scrollback past its capacity, with a prompt every 10 lines
+        let command = |t: &mut TestTerminal, n: usize| {
+            t.feed(format!("\x1b]133;A\x07$ cmd{}\r\n", n).as_bytes());
+            for line in 0..9 {
+                t.feed(format!("out{}.{}\r\n", n, line).as_bytes());
+            }
+        };
+        for n in 0..150 {
+            command(&mut t, n);
+        }
+        let top = |t: &TestTerminal| t.screen()[0].clone();
+        t.terminal.scroll_to_prompt(true);
+        assert_eq!(top(&t), "$ cmd149");
+        t.terminal.scroll_to_prompt(true);
+        assert_eq!(top(&t), "$ cmd148");
+        // New output evicts the oldest lines without moving the view
+        command(&mut t, 150);
+        assert_eq!(top(&t), "$ cmd148");
+        t.terminal.scroll_to_prompt(true);
+        assert_eq!(top(&t), "$ cmd147");
+        t.terminal.scroll_to_prompt(false);
+        assert_eq!(top(&t), "$ cmd148");
+        t.terminal.scroll_to_prompt(false);
+        assert_eq!(top(&t), "$ cmd149");
+        // So does resizing the screen
+        t.terminal.resize(Size { cols: 20, rows: 2 });
+        assert_eq!(top(&t), "$ cmd149");
+        t.terminal.resize(Size { cols: 20, rows: 6 });
+        assert_eq!(top(&t), "$ cmd149");
+    }
+
+    fn notification(title: &str, body: &str) -> Notification {
+        Notification {
+            title: title.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn iterm_notification() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]9;Build done; 3 warnings\x07");
+        assert_eq!(
+            t.notifications(),
+            [notification("", "Build done; 3 warnings")]
+        );
+        // ConEmu commands aren't notifications
+        t.feed(b"\x1b]9;4;1;50\x07\x1b]9;1;100\x07");
+        assert_eq!(t.notifications(), []);
+    }
+
+    #[test]
+    fn urxvt_notification() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]777;notify;Tests;12 passed; 0 failed\x1b\\");
+        assert_eq!(
+            t.notifications(),
+            [notification("Tests", "12 passed; 0 failed")]
+        );
+        t.feed(b"\x1b]777;other;t;b\x07");
+        assert_eq!(t.notifications(), []);
+    }
+
+    #[test]
+    fn kitty_notification() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]99;;Hello\x1b\\");
+        assert_eq!(t.notifications(), [notification("Hello", "")]);
+
+        // Chunks with d=0 are kept until the last one
+        t.feed(b"\x1b]99;i=1:d=0;Build\x1b\\");
+        t.feed(b"\x1b]99;i=1:d=0:p=body;Took \x1b\\");
+        assert_eq!(t.notifications(), []);
+        t.feed(b"\x1b]99;i=1:p=body:e=1;M3M=\x1b\\");
+        assert_eq!(t.notifications(), [notification("Build", "Took 3s")]);
+
+        // A chunk with another id drops the unfinished notification
+        t.feed(b"\x1b]99;i=2:d=0;Lost\x1b\\\x1b]99;i=3;Kept\x1b\\");
+        assert_eq!(t.notifications(), [notification("Kept", "")]);
+    }
+
+    #[test]
+    fn notifications_while_focused() {
+        let mut t = TestTerminal::new(Config::default());
+        t.terminal.set_focused(true);
+        t.feed(b"\x1b]9;hidden\x07\x1b]777;notify;t;hidden\x07\x1b]99;;hidden\x07");
+        assert_eq!(t.notifications(), []);
+        t.terminal.set_focused(false);
+        t.feed(b"\x1b]9;shown\x07");
+        assert_eq!(t.notifications(), [notification("", "shown")]);
+
+        let mut t = TestTerminal::new(Config {
+            suppress_focused_notifications: false,
+            ..Config::default()
+        });
+        t.terminal.set_focused(true);
+        t.feed(b"\x1b]9;shown\x07");
+        assert_eq!(t.notifications(), [notification("", "shown")]);
+    }
+
+    #[test]
+    fn clipboard_set() {
+        let mut t = TestTerminal::new(Config::default());
+        t.feed(b"\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(t.clipboard(Selection::Clipboard), "hello");
+        t.feed(b"\x1b]52;p;d29ybGQ=\x1b\\");
+        assert_eq!(t.clipboard(Selection::Primary), "world");
+        assert_eq!(t.clipboard(Selection::Clipboard), "hello");
+        // Invalid base64 leaves the clipboard alone
+        t.feed(b"\x1b]52;c;!!!\x07");
+        assert_eq!(t.clipboard(Selection::Clipboard), "hello");
+    }
+
+    #[test]
+    fn clipboard_set_denied() {
+        let mut t = TestTerminal::new(Config {
+            allow_clipboard_write: false,
+            ..Config::default()
+        });
+        t.feed(b"\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(t.clipboard(Selection::Clipboard), "");
+    }
+
+    #[test]
+    fn clipboard_max_size() {
+        let mut t = TestTerminal::new(Config {
+            clipboard_max_size: 3,
+            ..Config::default()
+        });
+        t.feed(b"\x1b]52;c;YWJj\x07");
+        assert_eq!(t.clipboard(Selection::Clipboard), "abc");
+        t.feed(b"\x1b]52;c;YWJjZA==\x07");
+        assert_eq!(t.clipboard(Selection::Clipboard), "abc");
+    }
+
+    #[test]
+    fn clipboard_query() {
+        let mut t = TestTerminal::new(Config {
+            allow_clipboard_read: true,
+            ..Config::default()
+        });
+        t.clipboard
+            .lock()
+            .unwrap()
+            .set(Selection::Clipboard, "hello".to_string())
+            .unwrap();
+        // The reply ends like the request
+        t.feed(b"\x1b]52;c;?\x07");
+        assert_eq!(t.output(), b"\x1b]52;c;aGVsbG8=\x07");
+        t.feed(b"\x1b]52;c;?\x1b\\");
+        assert_eq!(t.output(), b"\x1b]52;c;aGVsbG8=\x1b\\");
+        t.feed(b"\x1b]52;p;?\x07");
+        assert_eq!(t.output(), b"\x1b]52;p;\x07");
+    }
+
+    #[test]
+    fn clipboard_query_denied() {
+        let mut t = TestTerminal::new(Config::default());
+        t.clipboard
+            .lock()
+            .unwrap()
+            .set(Selection::Clipboard, "secret".to_string())
+            .unwrap();
+        t.feed(b"\x1b]52;c;?\x07");
+        assert_eq!(t.output(), b"");
+    }
+}
@@ -1,10 +1,8 @@
-use glyphon::Attrs;
-use glyphon::Buffer;
+use crate::quad::QuadRenderer;
+use crate::renderer::CellMetrics;
+use crate::renderer::RowBuffer;
 use glyphon::Cache;
-use glyphon::Family;
 use glyphon::FontSystem;
-use glyphon::Metrics;
-use glyphon::Shaping;
 use glyphon::SwashCache;
 use glyphon::TextAtlas;
 use glyphon::TextRenderer;
@@ -33,7 +31,10 @@ pub struct WindowState {
     pub viewport: glyphon::Viewport,
     pub atlas: glyphon::TextAtlas,
     pub text_renderer: glyphon::TextRenderer,
-    pub text_buffer: glyphon::Buffer,
+    pub quad_renderer: QuadRenderer,
+    /// One text buffer per row of the grid
+    pub row_buffers: Vec<RowBuffer>,
+    pub cell_metrics: CellMetrics,
 
     // Make sure that the winit window is last in the struct so that
     // it is dropped after the wgpu surface is dropped, otherwise the
@@ -44,7 +45,6 @@ pub struct WindowState {
 impl WindowState {
     pub async fn new(window: Arc<Window>) -> Self {
         let physical_size = window.inner_size();
-        let scale_factor = window.scale_factor();
 
         // Set up surface
         let instance = Instance::new(&InstanceDescriptor::default());
@@ -81,23 +81,8 @@ impl WindowState {
         let mut atlas = TextAtlas::new(&device, &queue, &cache, swapchain_format);
         let text_renderer =
             TextRenderer::new(&mut atlas, &device, MultisampleState::default(), None);
-        let mut text_buffer = Buffer::new(&mut font_system, Metrics::new(16.0, 16.0 * 1.2));
-
-        let physical_width = (physical_size.width as f64 * scale_factor) as f32;
-        let physical_height = (physical_size.height as f64 * scale_factor) as f32;
-
-        text_buffer.set_size(
-            &mut font_system,
-            Some(physical_width),
-            Some(physical_height),
-        );
-        text_buffer.set_text(
-            &mut font_system,
-            "Welcome to cosmicterm :)",
-            &Attrs::new().family(Family::Monospace),
-            Shaping::Advanced,
-        );
-        text_buffer.shape_until_scroll(&mut font_system, false);
+        let quad_renderer = QuadRenderer::new(&device, swapchain_format);
+        let cell_metrics = CellMetrics::measure(&mut font_system);
 
         Self {
             device,
@@ -109,7 +94,9 @@ impl WindowState {
             viewport,
             atlas,
             text_renderer,
-            text_buffer,
+            quad_renderer,
+            row_buffers: Vec::new(),
+            cell_metrics,
             window,
         }
     }